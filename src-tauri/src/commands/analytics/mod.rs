@@ -0,0 +1,87 @@
+use crate::{
+    commands::fansly::{command_error, FANSLY},
+    handlers::{
+        analytics::{
+            churn::{self, ChurnPoint, CohortRetention, Granularity},
+            forecast::{self, RenewalForecast},
+            tiers::{self, TierReport},
+        },
+        config,
+        history::{self, SyncSnapshot},
+        time::now_ms,
+    },
+};
+use tauri::AppHandle;
+
+const DEFAULT_FORECAST_DAYS: u32 = 7;
+
+#[tauri::command]
+pub async fn analytics_tier_report(app: AppHandle) -> Result<TierReport, String> {
+    let config = config::current().map_err(|e| e.to_string())?;
+
+    // Tiers come from the live profile, subscriptions from the last sync
+    let mut fansly = FANSLY.lock().await;
+    let profile = fansly.get_profile().await;
+    let profile = profile.map_err(|e| command_error(&app, &mut fansly, e))?;
+
+    log::info!(
+        "[commands::analytics::analytics_tier_report] Building tier report from {} synced subscriptions",
+        config.last_sync_data.subscribers.len()
+    );
+
+    Ok(tiers::tier_report(
+        &profile.response.account,
+        &config.last_sync_data.subscribers,
+    ))
+}
+
+fn load_history() -> Result<Vec<SyncSnapshot>, String> {
+    let history_dir = history::get_history_dir().map_err(|e| e.to_string())?;
+    history::load_all(&history_dir).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn analytics_churn(
+    granularity: Option<Granularity>,
+    since: Option<i64>,
+) -> Result<Vec<ChurnPoint>, String> {
+    let snapshots = load_history()?;
+
+    log::info!(
+        "[commands::analytics::analytics_churn] Building churn series from {} snapshots",
+        snapshots.len()
+    );
+
+    Ok(churn::churn_series(
+        &snapshots,
+        granularity.unwrap_or_default(),
+        since,
+    ))
+}
+
+#[tauri::command]
+pub async fn analytics_cohorts() -> Result<Vec<CohortRetention>, String> {
+    let snapshots = load_history()?;
+    Ok(churn::cohort_retention(&snapshots))
+}
+
+#[tauri::command]
+pub async fn analytics_forecast(days: Option<u32>) -> Result<RenewalForecast, String> {
+    let config = config::current().map_err(|e| e.to_string())?;
+
+    let report = forecast::forecast(
+        &config.last_sync_data.subscribers,
+        now_ms(),
+        days.unwrap_or(DEFAULT_FORECAST_DAYS),
+    );
+
+    log::info!(
+        "[commands::analytics::analytics_forecast] {} renewals ({}) and {} expirations coming up, {} at risk",
+        report.renewals.len(),
+        report.projected_revenue,
+        report.expirations.len(),
+        report.at_risk.len()
+    );
+
+    Ok(report)
+}
@@ -1,4 +1,25 @@
-use crate::handlers::config::{get_config_path, Config};
+use crate::handlers::config::{self, get_config_path, Config, FieldError, ImportSummary};
+use crate::handlers::overrides::overrides;
+use crate::handlers::scheduler::{self, SchedulerState};
+use serde_json::Value;
+use std::fs;
+use tauri::{AppHandle, Emitter};
+
+// A new sync token may fix whatever paused auto-sync, let the scheduler check again
+fn resume_if_sync_token_changed(previous: Option<String>, config: &Config) {
+    if previous.is_some_and(|token| token != config.sync_token)
+        && matches!(scheduler::status().state, SchedulerState::Paused(_))
+    {
+        scheduler::reset();
+    }
+}
+
+// Let every window know the config changed, so none of them keeps working on a stale copy
+fn emit_changed(app: &AppHandle, config: &Config) {
+    if let Err(e) = app.emit("config://changed", config) {
+        log::error!("[commands::config] Failed to emit config://changed: {}", e);
+    }
+}
 
 #[tauri::command]
 pub fn init_config() -> Result<(), String> {
@@ -10,7 +31,15 @@ pub fn init_config() -> Result<(), String> {
         config_path.display()
     );
 
-    Config::load_or_create(&config_path).map_err(|e| e.to_string())?;
+    let active = overrides().active();
+    if !active.is_empty() {
+        log::info!(
+            "[commands::config::init_config] Active overrides: {}",
+            active.join(", ")
+        );
+    }
+
+    config::current().map_err(|e| e.to_string())?;
     log::info!("[commands::config::init_config] Config initialized successfully");
     Ok(())
 }
@@ -18,11 +47,10 @@ pub fn init_config() -> Result<(), String> {
 #[tauri::command]
 pub fn get_config() -> Result<Config, String> {
     let config_path = get_config_path().map_err(|e| e.to_string())?;
-    let config = Config::load_or_create(&config_path).map_err(|e| e.to_string())?;
+    let config = config::current().map_err(|e| e.to_string())?;
 
     log::info!(
-        "[commands::config::get_config] Config loaded successfully: {:?} from path: {}",
-        config,
+        "[commands::config::get_config] Config loaded successfully from path: {}",
         config_path.display()
     );
 
@@ -30,14 +58,87 @@ pub fn get_config() -> Result<Config, String> {
 }
 
 #[tauri::command]
-pub fn save_config(config: Config) -> Result<(), String> {
+pub fn save_config(app: AppHandle, config: Config) -> Result<(), String> {
     let config_path = get_config_path().map_err(|e| e.to_string())?;
     log::info!(
-        "[commands::config::save_config] Saving config: {:?} to path: {}",
-        config,
+        "[commands::config::save_config] Saving config to path: {}",
         config_path.display()
     );
 
-    config.save(&config_path).map_err(|e| e.to_string())?;
+    let previous = config::current().ok().map(|config| config.sync_token);
+    let config = config::replace(config).map_err(|e| e.to_string())?;
+    resume_if_sync_token_changed(previous, &config);
+    emit_changed(&app, &config);
     Ok(())
 }
+
+// Change some settings without sending the whole config. `patch` is a JSON merge patch,
+// e.g. {"sync_interval": 2} or {"webhooks": []}.
+#[tauri::command]
+pub fn update_config(app: AppHandle, patch: Value) -> Result<Config, String> {
+    let fields: Vec<&str> = patch
+        .as_object()
+        .map(|patch| patch.keys().map(String::as_str).collect())
+        .unwrap_or_default();
+    log::info!(
+        "[commands::config::update_config] Updating config fields: {}",
+        fields.join(", ")
+    );
+
+    let previous = config::current().ok().map(|config| config.sync_token);
+    let config = config::update(&patch).map_err(|e| e.to_string())?;
+    resume_if_sync_token_changed(previous, &config);
+    emit_changed(&app, &config);
+    Ok(config)
+}
+
+// Check a config from the settings form without saving it
+#[tauri::command]
+pub fn validate_config(config: Config) -> Vec<FieldError> {
+    config.validate()
+}
+
+// Write the config to `path` as a bundle for another machine. Secrets are encrypted
+// with `passphrase` when one is given and left out otherwise.
+#[tauri::command]
+pub async fn export_config(path: String, passphrase: Option<String>) -> Result<(), String> {
+    log::info!(
+        "[commands::config::export_config] Exporting config to {}",
+        path
+    );
+
+    // Key derivation is slow on purpose, keep it off the async runtime
+    tauri::async_runtime::spawn_blocking(move || {
+        let bundle = config::export_bundle(passphrase.as_deref()).map_err(|e| e.to_string())?;
+        let contents = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+        fs::write(&path, contents).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Replace the config with a bundle made by export_config
+#[tauri::command]
+pub async fn import_config(
+    app: AppHandle,
+    path: String,
+    passphrase: Option<String>,
+) -> Result<ImportSummary, String> {
+    log::info!(
+        "[commands::config::import_config] Importing config from {}",
+        path
+    );
+
+    let previous = config::current().ok().map(|config| config.sync_token);
+    // Key derivation is slow on purpose, keep it off the async runtime
+    let summary = tauri::async_runtime::spawn_blocking(move || {
+        let bundle = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        config::import_bundle(&bundle, passphrase.as_deref()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    resume_if_sync_token_changed(previous, &summary.config);
+    emit_changed(&app, &summary.config);
+    Ok(summary)
+}
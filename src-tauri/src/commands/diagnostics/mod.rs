@@ -0,0 +1,30 @@
+use crate::handlers::diagnostics::{self, SystemInfo};
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+// Zip up redacted logs, config and recent sync history for a bug report, written to `path`
+#[tauri::command]
+pub async fn create_diagnostics_bundle(app: AppHandle, path: String) -> Result<(), String> {
+    log::info!(
+        "[commands::diagnostics::create_diagnostics_bundle] Creating diagnostics bundle at {}",
+        path
+    );
+
+    let system = SystemInfo {
+        app_version: app.package_info().version.to_string(),
+        tauri_version: tauri::VERSION.to_string(),
+        os_type: tauri_plugin_os::type_().to_string(),
+        os_version: tauri_plugin_os::version().to_string(),
+        os_family: tauri_plugin_os::family().to_string(),
+        arch: tauri_plugin_os::arch().to_string(),
+        locale: tauri_plugin_os::locale(),
+    };
+
+    // Reading and redacting the logs can take a moment, keep it off the async runtime
+    tauri::async_runtime::spawn_blocking(move || {
+        diagnostics::create_bundle(&PathBuf::from(path), &system)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
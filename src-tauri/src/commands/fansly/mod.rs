@@ -1,58 +1,348 @@
 use crate::{
-    handlers::fansly::Fansly,
-    structs::{FanslyAccountResponse, FanslyBaseResponse, SyncDataResponse},
+    handlers::{
+        chat::{self, SyncSummary},
+        config::{self, Config, SyncData},
+        delta::SyncDelta,
+        diagnostics::{self, SyncRun},
+        fansly::{Fansly, FanslyError, SyncOptions, TokenStatus},
+        history::{self, SyncSnapshot},
+        notifications::{self, Notice, NotificationSettings},
+        payload::PayloadFilter,
+        scheduler::{self, SchedulerState},
+        time::now_ms,
+        webhooks::{self, WebhookConfig, WebhookEvent},
+    },
+    structs::{
+        EarningsData, FanslyAccountResponse, FanslyBaseResponse, SubscriptionStatus,
+        SyncDataResponse, SyncTokenInfo,
+    },
 };
 use lazy_static::lazy_static;
-use serde_json::Value;
-use tokio::sync::Mutex;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+use tokio::sync::{mpsc, Mutex};
+use tracing::Instrument;
 
 lazy_static! {
-    static ref FANSLY: Mutex<Fansly> = Mutex::new(Fansly::new(None));
+    pub(crate) static ref FANSLY: Mutex<Fansly> = Mutex::new(Fansly::new(None));
+}
+
+fn load_config() -> Result<Config, String> {
+    config::current().map_err(|e| e.to_string())
+}
+
+// Fansly no longer accepts our token: remember that, stop auto-sync and ask the user to re-enter it
+fn handle_token_expired(app: &AppHandle, fansly: &mut Fansly) {
+    log::warn!("[commands::fansly] Fansly token is no longer valid, pausing auto-sync");
+
+    fansly.mark_token_invalid();
+    scheduler::pause("Fansly token is no longer valid".to_string());
+
+    if let Err(e) = app.emit("auth://expired", ()) {
+        log::error!("[commands::fansly] Failed to emit auth://expired: {}", e);
+    }
+
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("FanslySync: Fansly Token Expired")
+        .body("Your Fansly token is no longer valid. Please re-enter it in FanslySync to resume syncing.")
+        .show()
+    {
+        log::error!("[commands::fansly] Failed to send token expired notification: {}", e);
+    }
+}
+
+// Turn a Fansly error into the command's error message, handling an expired token on the way
+pub(crate) fn command_error(app: &AppHandle, fansly: &mut Fansly, e: FanslyError) -> String {
+    if let FanslyError::Unauthorized(_) = e {
+        handle_token_expired(app, fansly);
+    }
+    e.to_string()
 }
 
 #[tauri::command]
 pub async fn fansly_set_token(token: Option<String>) {
     FANSLY.lock().await.set_token(token);
+
+    // A new token may fix whatever paused auto-sync, let the scheduler check again
+    if matches!(scheduler::status().state, SchedulerState::Paused(_)) {
+        scheduler::reset();
+    }
+}
+
+#[tauri::command]
+pub async fn fansly_get_token_status() -> TokenStatus {
+    FANSLY.lock().await.token_status()
+}
+
+#[tauri::command]
+pub async fn fansly_validate_token(app: AppHandle) -> Result<TokenStatus, String> {
+    let mut fansly = FANSLY.lock().await;
+    let status = fansly.validate_token().await.map_err(|e| e.to_string())?;
+
+    if status == TokenStatus::Invalid {
+        handle_token_expired(&app, &mut fansly);
+    }
+
+    Ok(status)
 }
 
 #[tauri::command]
-pub async fn fansly_get_me() -> Result<FanslyBaseResponse<FanslyAccountResponse>, String> {
-    let fansly = FANSLY.lock().await;
+pub async fn fansly_get_me(
+    app: AppHandle,
+) -> Result<FanslyBaseResponse<FanslyAccountResponse>, String> {
+    let mut fansly = FANSLY.lock().await;
     let response = fansly.get_profile().await;
 
-    match response {
-        Ok(response) => Ok(response),
-        Err(e) => Err(e.to_string()),
+    response.map_err(|e| command_error(&app, &mut fansly, e))
+}
+
+async fn run_sync(
+    app: &AppHandle,
+    config: &Config,
+    options: &SyncOptions,
+    auto: bool,
+) -> Result<SyncDataResponse, String> {
+    let mut fansly = FANSLY.lock().await;
+
+    // A revoked token fails the first profile request, either the scheduler's or the sync's own,
+    // before anything else is fetched
+    let response = async {
+        // Automatic syncs only run once the scheduler is happy with the sync token
+        if auto {
+            scheduler::ensure_started(&fansly, &config.sync_token).await?;
+        }
+        fansly.sync(auto, options).await
+    }
+    .await;
+
+    response.map_err(|e| command_error(app, &mut fansly, e))
+}
+
+// Compare a finished sync with the one before it, saving it to the history on the way
+fn track_sync(
+    config: &Config,
+    options: &SyncOptions,
+    response: &SyncDataResponse,
+) -> Option<SyncDelta> {
+    let snapshot = history::snapshot_sync(response, &options.statuses());
+
+    let mut previous = None;
+    if config.sync_history_enabled {
+        // History is a nice to have, a failed read or write shouldn't fail the sync
+        match history::get_history_dir() {
+            Ok(history_dir) => {
+                previous = history::load_latest(&history_dir).unwrap_or_else(|e| {
+                    log::error!(
+                        "[commands::fansly] Failed to load last sync snapshot: {}",
+                        e
+                    );
+                    None
+                });
+
+                if let Err(e) = history::record(&history_dir, &snapshot, config.sync_history_limit)
+                {
+                    log::error!("[commands::fansly] Failed to save sync snapshot: {}", e);
+                }
+            }
+            Err(e) => log::error!("[commands::fansly] Failed to open sync history: {}", e),
+        }
+    }
+
+    // Without a stored snapshot the sync saved in the config is the best we have
+    let previous = previous.or_else(|| {
+        (config.last_sync > 0)
+            .then(|| SyncSnapshot::from_saved(&config.last_sync_data, config.last_sync as i64))
+    })?;
+
+    Some(SyncDelta::between(&previous, &snapshot))
+}
+
+// Keep the sync in the config as the last one, the next sync is compared against it when there's no history
+fn save_last_sync(app: &AppHandle, response: &SyncDataResponse) {
+    let patch = json!({
+        "last_sync": now_ms(),
+        "last_sync_data": SyncData {
+            followers: response.followers.clone(),
+            subscribers: response.subscribers.clone(),
+        },
+    });
+
+    match config::update(&patch) {
+        Ok(config) => {
+            if let Err(e) = app.emit("config://changed", &config) {
+                log::error!("[commands::fansly] Failed to emit config://changed: {}", e);
+            }
+        }
+        Err(e) => log::error!("[commands::fansly] Failed to save the last sync: {}", e),
+    }
+}
+
+fn show_notice(app: &AppHandle, notice: &Notice) {
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title(&notice.title)
+        .body(&notice.body)
+        .show()
+    {
+        log::error!("[commands::fansly] Failed to send notification: {}", e);
+    }
+}
+
+// One queue for all of a sync's webhook events. They are delivered in the background, in the
+// order they were queued, so slow receivers don't hold up the sync.
+fn webhook_queue(
+    client: reqwest::Client,
+    hooks: Vec<WebhookConfig>,
+) -> mpsc::UnboundedSender<(WebhookEvent, Value)> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<(WebhookEvent, Value)>();
+
+    if hooks.iter().any(|hook| hook.enabled) {
+        tauri::async_runtime::spawn(async move {
+            while let Some((event, data)) = receiver.recv().await {
+                webhooks::fire(&client, &hooks, event, data).await;
+            }
+        });
+    }
+
+    sender
+}
+
+fn queue_webhooks(
+    queue: &mpsc::UnboundedSender<(WebhookEvent, Value)>,
+    events: Vec<(WebhookEvent, Value)>,
+) {
+    for event in events {
+        // Nobody is listening when no hook is enabled
+        let _ = queue.send(event);
     }
 }
 
 #[tauri::command]
-pub async fn fansly_sync(auto: bool) -> Result<SyncDataResponse, String> {
-    let fansly = FANSLY.lock().await;
-    let response = fansly.sync(auto).await;
+pub async fn fansly_sync(
+    app: AppHandle,
+    auto: bool,
+    statuses: Option<Vec<SubscriptionStatus>>,
+) -> Result<SyncDataResponse, String> {
+    let config = load_config()?;
+    let settings = NotificationSettings::from_config(&config);
 
-    match response {
-        Ok(response) => Ok(response),
-        Err(e) => Err(e.to_string()),
+    let mut options = SyncOptions::from_config(&config);
+    // Statuses passed in by the caller win over the saved ones
+    if let Some(statuses) = statuses {
+        options.subscriber_statuses = statuses;
+    }
+
+    let webhook_client = FANSLY.lock().await.external_client();
+
+    let webhooks = webhook_queue(webhook_client.clone(), config.webhooks.clone());
+    queue_webhooks(
+        &webhooks,
+        vec![(WebhookEvent::SyncStarted, json!({ "auto": auto }))],
+    );
+
+    // Every log record of this sync carries the run id, so one run can be picked out of the logs
+    let run_id = uuid::Uuid::new_v4().to_string();
+    let span = tracing::info_span!("sync", run_id = %run_id, auto);
+
+    let started_at = now_ms();
+    let result = run_sync(&app, &config, &options, auto)
+        .instrument(span)
+        .await;
+
+    diagnostics::record_sync_run(SyncRun {
+        run_id,
+        started_at,
+        finished_at: now_ms(),
+        auto,
+        success: result.is_ok(),
+        followers: result
+            .as_ref()
+            .map_or(0, |response| response.followers.len()),
+        subscribers: result
+            .as_ref()
+            .map_or(0, |response| response.subscribers.len()),
+        error: result.as_ref().err().cloned(),
+    });
+
+    match &result {
+        Ok(response) => {
+            let mut events = vec![(
+                WebhookEvent::SyncCompleted,
+                json!({
+                    "auto": auto,
+                    "followers": response.followers.len(),
+                    "subscribers": response.subscribers.len(),
+                    "followCount": response.follow_count,
+                    "subscriberCount": response.subscriber_count,
+                }),
+            )];
+
+            let delta = track_sync(&config, &options, response);
+            save_last_sync(&app, response);
+            if let Some(delta) = &delta {
+                for notice in
+                    notifications::sync_notices(delta, &settings, response.accounts.as_ref())
+                {
+                    show_notice(&app, &notice);
+                }
+                events.extend(webhooks::delta_events(delta));
+            }
+
+            queue_webhooks(&webhooks, events);
+
+            if config.chat_webhooks.iter().any(|hook| hook.enabled) {
+                let hooks = config.chat_webhooks.clone();
+                let summary = SyncSummary::new(response, delta.as_ref());
+                tauri::async_runtime::spawn(async move {
+                    chat::post_summary(&webhook_client, &hooks, &summary).await;
+                });
+            }
+        }
+        Err(e) => {
+            // An expired token already got its own notification
+            let token_expired = FANSLY.lock().await.token_status() == TokenStatus::Invalid;
+            if settings.sync_failures && !token_expired {
+                show_notice(&app, &notifications::sync_failed_notice(auto, e));
+            }
+
+            queue_webhooks(
+                &webhooks,
+                vec![(
+                    WebhookEvent::SyncFailed,
+                    json!({ "auto": auto, "error": e }),
+                )],
+            );
+        }
     }
+
+    result
 }
 
 #[tauri::command]
 pub async fn fansly_upload_auto_sync_data(
+    app: AppHandle,
     data: SyncDataResponse,
     token: String,
 ) -> Result<(), String> {
-    let fansly: tokio::sync::MutexGuard<Fansly> = FANSLY.lock().await;
-    let response = fansly.upload_auto_sync_data(data, token).await;
+    let config = load_config()?;
+    let filter = PayloadFilter::from_config(&config);
+    let signing_secret =
+        Some(config.sync_signing_secret.as_str()).filter(|secret| !secret.is_empty());
 
-    match response {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e.to_string()),
-    }
+    let mut fansly: tokio::sync::MutexGuard<Fansly> = FANSLY.lock().await;
+    let response = fansly
+        .upload_auto_sync_data(data, token, &filter, signing_secret)
+        .await;
+
+    response.map_err(|e| command_error(&app, &mut fansly, e))
 }
 
 #[tauri::command]
-pub async fn fansly_check_sync_token(token: String) -> Result<Value, String> {
+pub async fn fansly_check_sync_token(token: String) -> Result<SyncTokenInfo, String> {
     let fansly: tokio::sync::MutexGuard<Fansly> = FANSLY.lock().await;
     let response = fansly.check_sync_token(token).await;
 
@@ -61,3 +351,15 @@ pub async fn fansly_check_sync_token(token: String) -> Result<Value, String> {
         Err(e) => Err(e.to_string()),
     }
 }
+
+#[tauri::command]
+pub async fn fansly_get_earnings(
+    app: AppHandle,
+    start: i64,
+    end: i64,
+) -> Result<EarningsData, String> {
+    let mut fansly = FANSLY.lock().await;
+    let response = fansly.get_earnings(start, end).await;
+
+    response.map_err(|e| command_error(&app, &mut fansly, e))
+}
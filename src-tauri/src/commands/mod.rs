@@ -1,3 +1,7 @@
+pub mod analytics;
 pub mod config;
+pub mod diagnostics;
 pub mod fansly;
+pub mod scheduler;
 pub mod utils;
+pub mod webhooks;
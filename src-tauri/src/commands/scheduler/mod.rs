@@ -0,0 +1,11 @@
+use crate::handlers::scheduler::{self, SchedulerStatus};
+
+#[tauri::command]
+pub fn scheduler_get_status() -> SchedulerStatus {
+    scheduler::status()
+}
+
+#[tauri::command]
+pub fn scheduler_reset() {
+    scheduler::reset();
+}
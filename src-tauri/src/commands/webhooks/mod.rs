@@ -0,0 +1,6 @@
+use crate::handlers::webhooks::{self, DeliveryRecord};
+
+#[tauri::command]
+pub fn webhooks_get_deliveries() -> Vec<DeliveryRecord> {
+    webhooks::deliveries()
+}
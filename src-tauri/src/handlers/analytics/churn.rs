@@ -0,0 +1,408 @@
+// Churn and retention over time, worked out by comparing consecutive sync snapshots from the history store.
+// Snapshots only show us what Fansly returned at sync time, so everything here is as precise as the sync interval.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::handlers::delta::SyncDelta;
+use crate::handlers::history::SyncSnapshot;
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    #[default]
+    Daily,
+    Weekly,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChurnPoint {
+    // Start of the day or week (UTC, weeks start on Monday) as a UNIX timestamp in milliseconds
+    pub period_start: i64,
+    pub new_subscribers: usize,
+    pub cancellations: usize,
+    pub expirations: usize,
+    // Active subscriptions whose renew date passed during the period, and how many of those renewed
+    pub renewals_due: usize,
+    pub renewals: usize,
+    // None when nothing was due for renewal
+    pub renewal_rate: Option<f64>,
+    pub new_followers: usize,
+    pub lost_followers: usize,
+    pub net_follower_growth: i64,
+    // Totals as of the last snapshot in the period
+    pub followers: usize,
+    pub active_subscribers: usize,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CohortRetention {
+    // The month subscribers in this cohort first subscribed, e.g. "2024-07"
+    pub cohort: String,
+    pub size: usize,
+    // Share of the cohort still active at the end of each month, starting with the cohort month.
+    // None for months we have no snapshot for.
+    pub retention: Vec<Option<f64>>,
+}
+
+fn period_start(timestamp: i64, granularity: Granularity) -> i64 {
+    let day = timestamp.div_euclid(DAY_MS);
+    match granularity {
+        Granularity::Daily => day * DAY_MS,
+        // 1970-01-01 was a Thursday, shift so weeks start on Monday
+        Granularity::Weekly => (day - (day + 3).rem_euclid(7)) * DAY_MS,
+    }
+}
+
+// Months since year 0 for a UNIX timestamp in milliseconds (UTC)
+fn month_index(timestamp: i64) -> i64 {
+    // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = timestamp.div_euclid(DAY_MS) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    year * 12 + month - 1
+}
+
+fn month_label(index: i64) -> String {
+    format!(
+        "{:04}-{:02}",
+        index.div_euclid(12),
+        index.rem_euclid(12) + 1
+    )
+}
+
+// Add up what changed between two consecutive snapshots. Subscriber and follower changes are counted
+// exactly like the ones we notify about, renewals are only tracked here.
+fn compare(previous: &SyncSnapshot, current: &SyncSnapshot, point: &mut ChurnPoint) {
+    let delta = SyncDelta::between(previous, current);
+
+    point.new_subscribers += delta.new_subscribers.len();
+    point.cancellations += delta.cancellations.len();
+    point.expirations += delta.expirations.len();
+    point.new_followers += delta.new_followers.len();
+    point.lost_followers += delta.lost_followers.len();
+
+    // A subscription missing because the sync asked for other statuses didn't fail to renew
+    if !previous.same_statuses_as(current) {
+        return;
+    }
+
+    let after = current.by_subscriber();
+    for (subscriber_id, was) in &previous.by_subscriber() {
+        let due = was.is_active()
+            && was.renew_date > previous.taken_at
+            && was.renew_date <= current.taken_at;
+        if !due {
+            continue;
+        }
+        point.renewals_due += 1;
+
+        let renewed = after.get(subscriber_id).is_some_and(|now| {
+            now.is_active() && (now.id != was.id || now.renew_date > was.renew_date)
+        });
+        if renewed {
+            point.renewals += 1;
+        }
+    }
+}
+
+fn point_for<'a>(
+    points: &'a mut BTreeMap<i64, ChurnPoint>,
+    snapshot: &SyncSnapshot,
+    granularity: Granularity,
+) -> &'a mut ChurnPoint {
+    let start = period_start(snapshot.taken_at, granularity);
+    let point = points.entry(start).or_insert_with(|| ChurnPoint {
+        period_start: start,
+        ..Default::default()
+    });
+
+    // Snapshots come in order, so the last one we see for a period wins
+    point.followers = snapshot.followers.len();
+    point.active_subscribers = snapshot
+        .subscriptions
+        .iter()
+        .filter(|s| s.is_active())
+        .count();
+    point
+}
+
+// Time series of subscriber and follower changes. `snapshots` must be sorted oldest first.
+pub fn churn_series(
+    snapshots: &[SyncSnapshot],
+    granularity: Granularity,
+    since: Option<i64>,
+) -> Vec<ChurnPoint> {
+    let mut points: BTreeMap<i64, ChurnPoint> = BTreeMap::new();
+
+    if let Some(first) = snapshots.first() {
+        point_for(&mut points, first, granularity);
+    }
+
+    for pair in snapshots.windows(2) {
+        compare(
+            &pair[0],
+            &pair[1],
+            point_for(&mut points, &pair[1], granularity),
+        );
+    }
+
+    points
+        .into_values()
+        .filter(|point| {
+            since.map_or(true, |since| {
+                point.period_start >= period_start(since, granularity)
+            })
+        })
+        .map(|mut point| {
+            point.net_follower_growth = point.new_followers as i64 - point.lost_followers as i64;
+            point.renewal_rate =
+                (point.renewals_due > 0).then(|| point.renewals as f64 / point.renewals_due as f64);
+            point
+        })
+        .collect()
+}
+
+// Monthly retention curves for subscribers grouped by the month they first subscribed.
+// `snapshots` must be sorted oldest first.
+pub fn cohort_retention(snapshots: &[SyncSnapshot]) -> Vec<CohortRetention> {
+    let Some(last) = snapshots.last() else {
+        return Vec::new();
+    };
+    let last_month = month_index(last.taken_at);
+
+    // The earliest subscription we have seen for each subscriber decides their cohort
+    let mut first_subscribed: HashMap<&str, i64> = HashMap::new();
+    for subscription in snapshots.iter().flat_map(|s| &s.subscriptions) {
+        if subscription.created_at <= 0 {
+            continue;
+        }
+        first_subscribed
+            .entry(subscription.subscriber_id.as_str())
+            .and_modify(|created_at| *created_at = (*created_at).min(subscription.created_at))
+            .or_insert(subscription.created_at);
+    }
+
+    let mut cohorts: BTreeMap<i64, Vec<&str>> = BTreeMap::new();
+    for (subscriber_id, created_at) in first_subscribed {
+        cohorts
+            .entry(month_index(created_at))
+            .or_default()
+            .push(subscriber_id);
+    }
+
+    // Who was active at the end of each month, going by the last snapshot taken in it
+    let mut active_by_month: BTreeMap<i64, HashSet<&str>> = BTreeMap::new();
+    for snapshot in snapshots {
+        let active = snapshot
+            .subscriptions
+            .iter()
+            .filter(|s| s.is_active())
+            .map(|s| s.subscriber_id.as_str())
+            .collect();
+        active_by_month.insert(month_index(snapshot.taken_at), active);
+    }
+
+    cohorts
+        .into_iter()
+        .map(|(cohort_month, members)| CohortRetention {
+            cohort: month_label(cohort_month),
+            size: members.len(),
+            retention: (cohort_month..=last_month)
+                .map(|month| {
+                    active_by_month.get(&month).map(|active| {
+                        let retained = members.iter().filter(|id| active.contains(*id)).count();
+                        retained as f64 / members.len() as f64
+                    })
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::delta::tests::{default_sync, subscription};
+    use crate::handlers::history::SubscriptionSnapshot;
+    use crate::structs::{AutoRenew, SubscriptionStatus};
+
+    // 2024-01-01T00:00:00Z, a Monday
+    const JAN_1_2024: i64 = 1_704_067_200_000;
+    const MAR_1_2024: i64 = 1_709_251_200_000;
+
+    fn month(year: i64, month: i64) -> i64 {
+        year * 12 + month - 1
+    }
+
+    #[test]
+    fn month_index_follows_the_calendar() {
+        assert_eq!(month_index(JAN_1_2024), month(2024, 1));
+        assert_eq!(month_index(JAN_1_2024 - 1), month(2023, 12));
+        // 2024 is a leap year, February has 29 days
+        assert_eq!(month_index(MAR_1_2024 - DAY_MS), month(2024, 2));
+        assert_eq!(month_index(MAR_1_2024), month(2024, 3));
+        assert_eq!(month_index(0), month(1970, 1));
+        assert_eq!(month_index(-1), month(1969, 12));
+
+        assert_eq!(month_label(month(2023, 12)), "2023-12");
+        assert_eq!(month_label(month(2024, 1)), "2024-01");
+    }
+
+    #[test]
+    fn periods_start_at_midnight_and_on_mondays() {
+        let wednesday_noon = JAN_1_2024 + 2 * DAY_MS + DAY_MS / 2;
+        assert_eq!(
+            period_start(wednesday_noon, Granularity::Daily),
+            JAN_1_2024 + 2 * DAY_MS
+        );
+        assert_eq!(
+            period_start(wednesday_noon, Granularity::Weekly),
+            JAN_1_2024
+        );
+        assert_eq!(period_start(JAN_1_2024, Granularity::Weekly), JAN_1_2024);
+
+        // The Sunday before belongs to the previous week, across the year boundary
+        assert_eq!(
+            period_start(JAN_1_2024 - 1, Granularity::Weekly),
+            JAN_1_2024 - 7 * DAY_MS
+        );
+        assert_eq!(
+            period_start(JAN_1_2024 - 1, Granularity::Daily),
+            JAN_1_2024 - DAY_MS
+        );
+        // 1970-01-01 was a Thursday
+        assert_eq!(period_start(0, Granularity::Weekly), -3 * DAY_MS);
+    }
+
+    #[test]
+    fn renewals_are_counted_when_they_fall_due() {
+        use AutoRenew::On;
+        use SubscriptionStatus::{Active, Expired};
+
+        let due = |id: &str, status, renew_date| SubscriptionSnapshot {
+            renew_date,
+            ..subscription(id, status, On)
+        };
+        let taken_at = JAN_1_2024;
+        let next_sync = taken_at + 2 * DAY_MS;
+
+        let previous = default_sync(
+            taken_at,
+            vec![
+                due("renewed", Active, taken_at + DAY_MS),
+                due("new-id", Active, taken_at + DAY_MS),
+                due("lapsed", Active, taken_at + DAY_MS),
+                due("later", Active, next_sync + DAY_MS),
+            ],
+        );
+        let current = default_sync(
+            next_sync,
+            vec![
+                due("renewed", Active, taken_at + 31 * DAY_MS),
+                SubscriptionSnapshot {
+                    id: "sub-new-id-2".to_string(),
+                    ..due("new-id", Active, taken_at + DAY_MS)
+                },
+                due("lapsed", Expired, taken_at + DAY_MS),
+                due("later", Active, next_sync + DAY_MS),
+            ],
+        );
+
+        let series = churn_series(&[previous, current], Granularity::Weekly, None);
+        assert_eq!(series.len(), 1);
+
+        let point = &series[0];
+        assert_eq!(point.period_start, JAN_1_2024);
+        assert_eq!(point.renewals_due, 3);
+        assert_eq!(point.renewals, 2);
+        assert_eq!(point.renewal_rate, Some(2.0 / 3.0));
+        assert_eq!(point.expirations, 1);
+        assert_eq!(point.active_subscribers, 3);
+    }
+
+    #[test]
+    fn periods_without_renewals_have_no_rate() {
+        let series = churn_series(
+            &[
+                default_sync(JAN_1_2024, vec![]),
+                default_sync(JAN_1_2024 + DAY_MS, vec![]),
+            ],
+            Granularity::Daily,
+            None,
+        );
+
+        assert_eq!(series.len(), 2);
+        assert!(series.iter().all(|point| point.renewal_rate.is_none()));
+        assert!(churn_series(&[], Granularity::Daily, None).is_empty());
+    }
+
+    #[test]
+    fn cohorts_are_tracked_across_the_year_boundary() {
+        use AutoRenew::On;
+        use SubscriptionStatus::{Active, Expired};
+
+        let subscribed = |id: &str, status, created_at| SubscriptionSnapshot {
+            created_at,
+            ..subscription(id, status, On)
+        };
+        let december = JAN_1_2024 - 10 * DAY_MS;
+
+        let snapshots = [
+            default_sync(december, vec![subscribed("a", Active, december)]),
+            default_sync(
+                JAN_1_2024 + 14 * DAY_MS,
+                vec![
+                    subscribed("a", Active, december),
+                    subscribed("b", Active, JAN_1_2024),
+                ],
+            ),
+            // Nothing synced in February
+            default_sync(
+                MAR_1_2024 + 9 * DAY_MS,
+                vec![
+                    subscribed("a", Expired, december),
+                    subscribed("b", Active, JAN_1_2024),
+                ],
+            ),
+        ];
+
+        let cohorts = cohort_retention(&snapshots);
+        assert_eq!(
+            cohorts,
+            vec![
+                CohortRetention {
+                    cohort: "2023-12".to_string(),
+                    size: 1,
+                    retention: vec![Some(1.0), Some(1.0), None, Some(0.0)],
+                },
+                CohortRetention {
+                    cohort: "2024-01".to_string(),
+                    size: 1,
+                    retention: vec![Some(1.0), None, Some(1.0)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_snapshots_or_creation_dates_means_no_cohorts() {
+        assert!(cohort_retention(&[]).is_empty());
+
+        // Without a creation date we can't tell which cohort someone belongs to
+        let snapshot = default_sync(
+            JAN_1_2024,
+            vec![subscription("a", SubscriptionStatus::Active, AutoRenew::On)],
+        );
+        assert!(cohort_retention(&[snapshot]).is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+// Looks ahead at the synced subscriptions: who renews and who lapses in the next few days,
+// and how much the renewals should bring in.
+use serde::{Deserialize, Serialize};
+
+use crate::structs::{AutoRenew, BillingCycle, Money, Subscription, SubscriptionStatus};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForecastEvent {
+    #[default]
+    Renewal,
+    Expiration,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForecastEntry {
+    pub subscription_id: String,
+    pub subscriber_id: String,
+    pub subscription_tier_id: String,
+    pub subscription_tier_name: String,
+    pub event: ForecastEvent,
+    // When the subscription renews or lapses (UNIX timestamp in milliseconds)
+    pub date: i64,
+    pub auto_renew: AutoRenew,
+    pub billing_cycle: BillingCycle,
+    // What the renewal will charge, falling back to the current price if Fansly didn't give us one
+    pub renew_price: Money,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenewalForecast {
+    pub start: i64,
+    pub end: i64,
+    // Both sorted by date, soonest first
+    pub renewals: Vec<ForecastEntry>,
+    pub expirations: Vec<ForecastEntry>,
+    pub projected_revenue: Money,
+    // Still active but with auto-renew off, these lapse unless the subscriber turns it back on
+    pub at_risk: Vec<ForecastEntry>,
+}
+
+fn entry_for(subscription: &Subscription, event: ForecastEvent, date: i64) -> ForecastEntry {
+    let renew_price = if subscription.renew_price.cents() > 0 {
+        subscription.renew_price
+    } else {
+        subscription.price
+    };
+
+    ForecastEntry {
+        subscription_id: subscription.id.clone(),
+        subscriber_id: subscription.subscriber_id.clone(),
+        subscription_tier_id: subscription.subscription_tier_id.clone(),
+        subscription_tier_name: subscription.subscription_tier_name.clone(),
+        event,
+        date,
+        auto_renew: subscription.auto_renew,
+        billing_cycle: subscription.billing_cycle,
+        renew_price,
+    }
+}
+
+// Renewals and expirations between `now` and `days` days from now
+pub fn forecast(subscriptions: &[Subscription], now: i64, days: u32) -> RenewalForecast {
+    let end = now + i64::from(days) * DAY_MS;
+    let in_window = |date: i64| date > 0 && date >= now && date <= end;
+
+    let mut report = RenewalForecast {
+        start: now,
+        end,
+        ..Default::default()
+    };
+
+    for subscription in subscriptions {
+        let renews = subscription.auto_renew == AutoRenew::On
+            && subscription.status == SubscriptionStatus::Active;
+
+        if renews {
+            if in_window(subscription.renew_date) {
+                report.renewals.push(entry_for(
+                    subscription,
+                    ForecastEvent::Renewal,
+                    subscription.renew_date,
+                ));
+            }
+            continue;
+        }
+
+        if subscription.status == SubscriptionStatus::Expired {
+            continue;
+        }
+
+        // Without auto-renew the subscription runs until ends_at, or the renew date if that's all we have
+        let lapses_at = if subscription.ends_at > 0 {
+            subscription.ends_at
+        } else {
+            subscription.renew_date
+        };
+
+        if in_window(lapses_at) {
+            let entry = entry_for(subscription, ForecastEvent::Expiration, lapses_at);
+            if subscription.status == SubscriptionStatus::Active {
+                report.at_risk.push(entry.clone());
+            }
+            report.expirations.push(entry);
+        }
+    }
+
+    report.renewals.sort_by_key(|entry| entry.date);
+    report.expirations.sort_by_key(|entry| entry.date);
+    report.at_risk.sort_by_key(|entry| entry.date);
+    report.projected_revenue = report.renewals.iter().map(|entry| entry.renew_price).sum();
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_721_988_883_000;
+
+    fn subscription(
+        id: &str,
+        status: SubscriptionStatus,
+        auto_renew: AutoRenew,
+        renew_date: i64,
+    ) -> Subscription {
+        Subscription {
+            id: id.to_string(),
+            subscriber_id: format!("fan-{}", id),
+            status,
+            auto_renew,
+            renew_date,
+            price: Money::from_cents(700),
+            ..Default::default()
+        }
+    }
+
+    fn ids(entries: &[ForecastEntry]) -> Vec<&str> {
+        entries
+            .iter()
+            .map(|entry| entry.subscription_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn renewals_in_the_window_are_projected() {
+        use AutoRenew::On;
+        use SubscriptionStatus::{Active, Expired};
+
+        let subscriptions = [
+            subscription("later", Active, On, NOW + 3 * DAY_MS),
+            Subscription {
+                renew_price: Money::from_cents(500),
+                ..subscription("discounted", Active, On, NOW + DAY_MS)
+            },
+            subscription("edge", Active, On, NOW + 7 * DAY_MS),
+            subscription("outside", Active, On, NOW + 7 * DAY_MS + 1),
+            subscription("past", Active, On, NOW - 1),
+            subscription("unknown", Active, On, 0),
+            subscription("expired", Expired, On, NOW + DAY_MS),
+        ];
+
+        let report = forecast(&subscriptions, NOW, 7);
+        assert_eq!(report.end, NOW + 7 * DAY_MS);
+        assert_eq!(ids(&report.renewals), vec!["discounted", "later", "edge"]);
+        // The renew price when Fansly gave us one, the current price otherwise
+        assert_eq!(report.renewals[0].renew_price, Money::from_cents(500));
+        assert_eq!(report.projected_revenue, Money::from_cents(1900));
+        assert!(report.expirations.is_empty());
+        assert!(report.at_risk.is_empty());
+    }
+
+    #[test]
+    fn subscriptions_without_auto_renew_lapse() {
+        use AutoRenew::Off;
+        use SubscriptionStatus::{Active, Expired, Unknown};
+
+        let subscriptions = [
+            // ends_at wins over the renew date
+            Subscription {
+                ends_at: NOW + 2 * DAY_MS,
+                ..subscription("ending", Active, Off, NOW + 30 * DAY_MS)
+            },
+            subscription("renew-date-only", Active, Off, NOW + DAY_MS),
+            // Not active, so it lapses without being at risk
+            subscription("pending", Unknown(1), Off, NOW + 3 * DAY_MS),
+            subscription("gone", Expired, Off, NOW + DAY_MS),
+        ];
+
+        let report = forecast(&subscriptions, NOW, 7);
+        assert!(report.renewals.is_empty());
+        assert_eq!(report.projected_revenue, Money::default());
+        assert_eq!(
+            ids(&report.expirations),
+            vec!["renew-date-only", "ending", "pending"]
+        );
+        assert_eq!(report.expirations[1].date, NOW + 2 * DAY_MS);
+        assert_eq!(ids(&report.at_risk), vec!["renew-date-only", "ending"]);
+        assert!(report
+            .expirations
+            .iter()
+            .all(|entry| entry.event == ForecastEvent::Expiration));
+    }
+
+    #[test]
+    fn an_empty_window_forecasts_nothing() {
+        let subscriptions = [subscription(
+            "a",
+            SubscriptionStatus::Active,
+            AutoRenew::On,
+            NOW + DAY_MS,
+        )];
+
+        let report = forecast(&subscriptions, NOW, 0);
+        assert!(report.renewals.is_empty());
+        assert_eq!(report.start, report.end);
+        assert_eq!(forecast(&[], NOW, 7).projected_revenue, Money::default());
+    }
+}
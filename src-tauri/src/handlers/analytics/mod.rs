@@ -0,0 +1,5 @@
+// Reports derived from synced data. Everything in here is pure computation over what a sync
+// already fetched, nothing talks to Fansly directly.
+pub mod churn;
+pub mod forecast;
+pub mod tiers;
@@ -0,0 +1,343 @@
+// Per-tier breakdown of the synced subscriptions: how many active subscribers each tier has,
+// which billing cycles they are on, what they pay and how close the tier is to its cap.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::structs::{Account, BillingCycle, Money, Subscription, SubscriptionStatus};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TierReport {
+    pub total_active: usize,
+    pub tiers: Vec<TierStats>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TierStats {
+    pub tier_id: String,
+    pub name: String,
+    pub color: String,
+    pub price: Money,
+    // 0 means the tier has no cap
+    pub max_subscribers: i64,
+    pub active_subscribers: usize,
+    // Share of all active subscribers that are on this tier, 0.0 - 1.0
+    pub share_of_active: f64,
+    // Active subscribers divided by the cap, None for uncapped tiers
+    pub capacity_used: Option<f64>,
+    // Average of what active subscribers pay per billing period
+    pub average_effective_price: Money,
+    // Same, normalized to a 30 day month so tiers with different cycles compare
+    pub average_monthly_price: Money,
+    pub billing_cycles: Vec<BillingCycleShare>,
+    pub promo_subscribers: usize,
+    pub promo_share: f64,
+    // Total uses of all promos on this tier's plans, as reported by Fansly
+    pub promo_uses: i64,
+    // False for tiers we only know about from subscriptions (e.g. deleted tiers)
+    pub on_profile: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BillingCycleShare {
+    pub billing_cycle: BillingCycle,
+    pub subscribers: usize,
+    pub share: f64,
+}
+
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+fn average(amounts: &[i64]) -> Money {
+    if amounts.is_empty() {
+        return Money::default();
+    }
+    Money::from_cents(amounts.iter().sum::<i64>() / amounts.len() as i64)
+}
+
+// What a subscription at `price` per billing cycle costs per 30 days
+pub(crate) fn monthly_cents(price: &Money, billing_cycle: BillingCycle) -> i64 {
+    let days = billing_cycle.days();
+    if days <= 0 {
+        price.cents()
+    } else {
+        price.cents() * 30 / days
+    }
+}
+
+fn stats_for(
+    tier_id: &str,
+    active: &[&Subscription],
+    total_active: usize,
+    max_subscribers: i64,
+) -> TierStats {
+    let mut cycles: BTreeMap<i64, usize> = BTreeMap::new();
+    for subscription in active {
+        *cycles.entry(subscription.billing_cycle.days()).or_default() += 1;
+    }
+
+    let promo_subscribers = active
+        .iter()
+        .filter(|subscription| subscription.promo_id.is_some())
+        .count();
+
+    TierStats {
+        tier_id: tier_id.to_string(),
+        max_subscribers,
+        active_subscribers: active.len(),
+        share_of_active: ratio(active.len(), total_active),
+        capacity_used: (max_subscribers > 0).then(|| active.len() as f64 / max_subscribers as f64),
+        average_effective_price: average(
+            &active.iter().map(|s| s.price.cents()).collect::<Vec<_>>(),
+        ),
+        average_monthly_price: average(
+            &active
+                .iter()
+                .map(|s| monthly_cents(&s.price, s.billing_cycle))
+                .collect::<Vec<_>>(),
+        ),
+        billing_cycles: cycles
+            .into_iter()
+            .map(|(days, subscribers)| BillingCycleShare {
+                billing_cycle: BillingCycle::from(days),
+                subscribers,
+                share: ratio(subscribers, active.len()),
+            })
+            .collect(),
+        promo_subscribers,
+        promo_share: ratio(promo_subscribers, active.len()),
+        ..Default::default()
+    }
+}
+
+pub fn tier_report(account: &Account, subscriptions: &[Subscription]) -> TierReport {
+    let active: Vec<&Subscription> = subscriptions
+        .iter()
+        .filter(|subscription| subscription.status == SubscriptionStatus::Active)
+        .collect();
+
+    let mut by_tier: BTreeMap<&str, Vec<&Subscription>> = BTreeMap::new();
+    for subscription in &active {
+        by_tier
+            .entry(subscription.subscription_tier_id.as_str())
+            .or_default()
+            .push(subscription);
+    }
+
+    let mut tiers = Vec::new();
+
+    // Tiers on the profile first, in the order the creator arranged them
+    let mut profile_tiers: Vec<_> = account.subscription_tiers.iter().collect();
+    profile_tiers.sort_by_key(|tier| tier.pos);
+    for tier in profile_tiers {
+        let tier_active = by_tier.remove(tier.id.as_str()).unwrap_or_default();
+        let mut stats = stats_for(&tier.id, &tier_active, active.len(), tier.max_subscribers);
+
+        stats.name = tier.name.clone();
+        stats.color = tier.color.clone();
+        stats.price = tier.price;
+        stats.promo_uses = tier
+            .plans
+            .iter()
+            .flat_map(|plan| plan.promos.iter())
+            .map(|promo| promo.uses)
+            .sum();
+        stats.on_profile = true;
+
+        tiers.push(stats);
+    }
+
+    // Whatever is left belongs to tiers that are no longer on the profile
+    for (tier_id, tier_active) in by_tier {
+        let mut stats = stats_for(tier_id, &tier_active, active.len(), 0);
+        if let Some(first) = tier_active.first() {
+            stats.name = first.subscription_tier_name.clone();
+            stats.color = first.subscription_tier_color.clone();
+        }
+        tiers.push(stats);
+    }
+
+    TierReport {
+        total_active: active.len(),
+        tiers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{Plan, Promo, SubscriptionTier};
+
+    fn tier(id: &str, pos: i64, max_subscribers: i64, promo_uses: &[i64]) -> SubscriptionTier {
+        SubscriptionTier {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            pos,
+            price: Money::from_cents(700),
+            max_subscribers,
+            plans: vec![Plan {
+                promos: promo_uses
+                    .iter()
+                    .map(|uses| Promo {
+                        uses: *uses,
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn subscription(tier_id: &str, price: i64, billing_cycle: BillingCycle) -> Subscription {
+        Subscription {
+            subscription_tier_id: tier_id.to_string(),
+            status: SubscriptionStatus::Active,
+            price: Money::from_cents(price),
+            billing_cycle,
+            ..Default::default()
+        }
+    }
+
+    fn report() -> TierReport {
+        let account = Account {
+            subscription_tiers: vec![
+                tier("basic", 1, 10, &[3, 2]),
+                tier("vip", 0, 0, &[]),
+                tier("empty", 2, 5, &[]),
+            ],
+            ..Default::default()
+        };
+        let subscriptions = vec![
+            subscription("basic", 700, BillingCycle::Monthly),
+            subscription("basic", 700, BillingCycle::Monthly),
+            Subscription {
+                promo_id: Some("promo".to_string()),
+                ..subscription("basic", 1800, BillingCycle::Quarterly)
+            },
+            Subscription {
+                status: SubscriptionStatus::Expired,
+                ..subscription("basic", 700, BillingCycle::Monthly)
+            },
+            subscription("vip", 2000, BillingCycle::Monthly),
+            // How Fansly says there is no promo
+            serde_json::from_value(serde_json::json!({
+                "id": "vip-2",
+                "subscriberId": "fan",
+                "subscriptionTierId": "vip",
+                "status": 3,
+                "price": 2000,
+                "billingCycle": 30,
+                "promoId": "0",
+            }))
+            .unwrap(),
+            Subscription {
+                subscription_tier_name: "Retired".to_string(),
+                ..subscription("retired", 500, BillingCycle::Monthly)
+            },
+        ];
+
+        tier_report(&account, &subscriptions)
+    }
+
+    #[test]
+    fn tiers_follow_the_profile_then_tiers_that_are_gone() {
+        let report = report();
+        assert_eq!(report.total_active, 6);
+
+        let order: Vec<(&str, bool)> = report
+            .tiers
+            .iter()
+            .map(|tier| (tier.tier_id.as_str(), tier.on_profile))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("vip", true),
+                ("basic", true),
+                ("empty", true),
+                ("retired", false)
+            ]
+        );
+        assert_eq!(report.tiers[3].name, "Retired");
+        assert_eq!(report.tiers[3].max_subscribers, 0);
+    }
+
+    #[test]
+    fn tier_stats_only_count_active_subscribers() {
+        let report = report();
+        let basic = &report.tiers[1];
+
+        assert_eq!(basic.name, "BASIC");
+        assert_eq!(basic.active_subscribers, 3);
+        assert_eq!(basic.share_of_active, 0.5);
+        assert_eq!(basic.capacity_used, Some(0.3));
+        assert_eq!(basic.average_effective_price, Money::from_cents(1066));
+        // 700, 700 and 1800 per 90 days = 600 per 30
+        assert_eq!(basic.average_monthly_price, Money::from_cents(666));
+        assert_eq!(
+            basic.billing_cycles,
+            vec![
+                BillingCycleShare {
+                    billing_cycle: BillingCycle::Monthly,
+                    subscribers: 2,
+                    share: 2.0 / 3.0,
+                },
+                BillingCycleShare {
+                    billing_cycle: BillingCycle::Quarterly,
+                    subscribers: 1,
+                    share: 1.0 / 3.0,
+                },
+            ]
+        );
+        assert_eq!(basic.promo_subscribers, 1);
+        assert_eq!(basic.promo_share, 1.0 / 3.0);
+        assert_eq!(basic.promo_uses, 5);
+
+        // Uncapped tiers have no capacity to fill
+        let vip = &report.tiers[0];
+        assert_eq!(vip.capacity_used, None);
+        assert_eq!(vip.active_subscribers, 2);
+        assert_eq!(vip.promo_subscribers, 0);
+    }
+
+    #[test]
+    fn empty_tiers_and_reports_dont_divide_by_zero() {
+        let report = report();
+        let empty = &report.tiers[2];
+        assert_eq!(empty.active_subscribers, 0);
+        assert_eq!(empty.share_of_active, 0.0);
+        assert_eq!(empty.capacity_used, Some(0.0));
+        assert_eq!(empty.average_effective_price, Money::default());
+        assert_eq!(empty.promo_share, 0.0);
+        assert!(empty.billing_cycles.is_empty());
+
+        let report = tier_report(&Account::default(), &[]);
+        assert_eq!(report.total_active, 0);
+        assert!(report.tiers.is_empty());
+    }
+
+    #[test]
+    fn custom_billing_cycles_are_normalized_to_30_days() {
+        assert_eq!(
+            monthly_cents(&Money::from_cents(6000), BillingCycle::Yearly),
+            493
+        );
+        assert_eq!(
+            monthly_cents(&Money::from_cents(1000), BillingCycle::Custom(15)),
+            2000
+        );
+        // No known length, taken as monthly
+        assert_eq!(
+            monthly_cents(&Money::from_cents(1000), BillingCycle::Custom(0)),
+            1000
+        );
+    }
+}
@@ -0,0 +1,387 @@
+// Sync summaries for Discord and Slack incoming webhooks. The summary is worked out once,
+// then rendered as a Discord embed or Slack blocks depending on where it's going.
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::handlers::delta::{SubscriberEvent, SyncDelta};
+use crate::handlers::webhooks;
+use crate::structs::{display_name, SubscriptionStatus, SyncDataResponse};
+
+// Names listed per section before we cut the list short
+const MAX_LISTED: usize = 10;
+const TOP_TIERS: usize = 3;
+const TITLE: &str = "FanslySync: Sync Complete";
+// The FanslySync blue, as used for the default tier color
+const EMBED_COLOR: u32 = 0x2699f7;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatPlatform {
+    #[default]
+    Discord,
+    Slack,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatWebhook {
+    pub platform: ChatPlatform,
+    // The incoming webhook URL from Discord or Slack
+    pub url: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct SyncSummary {
+    pub follow_count: i64,
+    pub subscriber_count: i64,
+    pub active_subscribers: usize,
+    // None when there was no previous sync to compare against
+    pub follower_change: Option<(usize, usize)>,
+    pub new_subscribers: Vec<String>,
+    pub lost_subscribers: Vec<String>,
+    // Tier name and active subscribers, busiest first
+    pub top_tiers: Vec<(String, usize)>,
+}
+
+impl SyncSummary {
+    pub fn new(data: &SyncDataResponse, delta: Option<&SyncDelta>) -> Self {
+        let accounts = data.accounts.as_ref();
+        let names = |events: &[SubscriberEvent]| -> Vec<String> {
+            events
+                .iter()
+                .map(|event| display_name(&event.subscriber_id, accounts))
+                .collect()
+        };
+
+        let active: Vec<_> = data
+            .subscribers
+            .iter()
+            .filter(|subscription| subscription.status == SubscriptionStatus::Active)
+            .collect();
+
+        // Prefer the tier report when the sync made one, it knows the tier names from the profile
+        let mut top_tiers: Vec<(String, usize)> = match &data.tier_report {
+            Some(report) => report
+                .tiers
+                .iter()
+                .map(|tier| (tier.name.clone(), tier.active_subscribers))
+                .collect(),
+            None => {
+                let mut by_tier: HashMap<&str, usize> = HashMap::new();
+                for subscription in &active {
+                    *by_tier
+                        .entry(subscription.subscription_tier_name.as_str())
+                        .or_default() += 1;
+                }
+                by_tier
+                    .into_iter()
+                    .map(|(name, count)| (name.to_string(), count))
+                    .collect()
+            }
+        };
+        top_tiers.retain(|(_, count)| *count > 0);
+        top_tiers.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_tiers.truncate(TOP_TIERS);
+
+        Self {
+            follow_count: data.follow_count,
+            subscriber_count: data.subscriber_count,
+            active_subscribers: active.len(),
+            follower_change: delta
+                .map(|delta| (delta.new_followers.len(), delta.lost_followers.len())),
+            new_subscribers: delta
+                .map(|delta| names(&delta.new_subscribers))
+                .unwrap_or_default(),
+            lost_subscribers: delta
+                .map(|delta| {
+                    let mut lost = names(&delta.cancellations);
+                    lost.extend(names(&delta.expirations));
+                    lost
+                })
+                .unwrap_or_default(),
+            top_tiers,
+        }
+    }
+
+    fn followers_line(&self) -> String {
+        match self.follower_change {
+            Some((gained, lost)) => format!("{} (+{} / -{})", self.follow_count, gained, lost),
+            None => self.follow_count.to_string(),
+        }
+    }
+
+    fn subscribers_line(&self) -> String {
+        format!(
+            "{} ({} active in this sync)",
+            self.subscriber_count, self.active_subscribers
+        )
+    }
+
+    fn tiers_text(&self) -> String {
+        if self.top_tiers.is_empty() {
+            return "None".to_string();
+        }
+        self.top_tiers
+            .iter()
+            .map(|(name, count)| format!("{}: {}", name, count))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn list_text(names: &[String]) -> String {
+    if names.is_empty() {
+        return "None".to_string();
+    }
+
+    let mut text = names
+        .iter()
+        .take(MAX_LISTED)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > MAX_LISTED {
+        text.push_str(&format!(" and {} more", names.len() - MAX_LISTED));
+    }
+    text
+}
+
+pub fn discord_payload(summary: &SyncSummary) -> Value {
+    json!({
+        "username": "FanslySync",
+        "embeds": [{
+            "title": TITLE,
+            "color": EMBED_COLOR,
+            "fields": [
+                { "name": "Followers", "value": summary.followers_line(), "inline": true },
+                { "name": "Subscribers", "value": summary.subscribers_line(), "inline": true },
+                {
+                    "name": format!("New Subscribers ({})", summary.new_subscribers.len()),
+                    "value": list_text(&summary.new_subscribers),
+                },
+                {
+                    "name": format!("Lost Subscribers ({})", summary.lost_subscribers.len()),
+                    "value": list_text(&summary.lost_subscribers),
+                },
+                { "name": "Top Tiers", "value": summary.tiers_text() },
+            ],
+        }],
+    })
+}
+
+pub fn slack_payload(summary: &SyncSummary) -> Value {
+    let section = |title: String, text: String| {
+        json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*{}*\n{}", title, text) },
+        })
+    };
+
+    json!({
+        // Shown in notifications and by clients that can't render blocks
+        "text": TITLE,
+        "blocks": [
+            { "type": "header", "text": { "type": "plain_text", "text": TITLE } },
+            {
+                "type": "section",
+                "fields": [
+                    { "type": "mrkdwn", "text": format!("*Followers*\n{}", summary.followers_line()) },
+                    { "type": "mrkdwn", "text": format!("*Subscribers*\n{}", summary.subscribers_line()) },
+                ],
+            },
+            section(
+                format!("New Subscribers ({})", summary.new_subscribers.len()),
+                list_text(&summary.new_subscribers),
+            ),
+            section(
+                format!("Lost Subscribers ({})", summary.lost_subscribers.len()),
+                list_text(&summary.lost_subscribers),
+            ),
+            section("Top Tiers".to_string(), summary.tiers_text()),
+        ],
+    })
+}
+
+// Post the summary to every enabled chat webhook, one after another
+pub async fn post_summary(client: &reqwest::Client, hooks: &[ChatWebhook], summary: &SyncSummary) {
+    for hook in hooks.iter().filter(|hook| hook.enabled) {
+        let (event, payload) = match hook.platform {
+            ChatPlatform::Discord => ("chat.discord", discord_payload(summary)),
+            ChatPlatform::Slack => ("chat.slack", slack_payload(summary)),
+        };
+
+        webhooks::deliver(client, &hook.url, event, || {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            (headers, payload.to_string())
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::analytics::tiers::{TierReport, TierStats};
+    use crate::structs::{AccountSummary, Subscription};
+    use std::collections::BTreeMap;
+
+    fn event(subscriber_id: &str) -> SubscriberEvent {
+        SubscriberEvent {
+            subscriber_id: subscriber_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn active(tier_name: &str) -> Subscription {
+        Subscription {
+            subscription_tier_name: tier_name.to_string(),
+            status: SubscriptionStatus::Active,
+            ..Default::default()
+        }
+    }
+
+    fn summary() -> SyncSummary {
+        let data = SyncDataResponse {
+            subscribers: vec![active("Basic"), active("Basic"), active("VIP")],
+            accounts: Some(BTreeMap::from([(
+                "1".to_string(),
+                AccountSummary {
+                    id: "1".to_string(),
+                    username: "fan".to_string(),
+                    ..Default::default()
+                },
+            )])),
+            follow_count: 120,
+            subscriber_count: 4,
+            ..Default::default()
+        };
+        let delta = SyncDelta {
+            new_subscribers: vec![event("1"), event("2")],
+            cancellations: vec![event("3")],
+            expirations: vec![event("4")],
+            new_followers: vec!["5".to_string(), "6".to_string()],
+            lost_followers: vec!["7".to_string()],
+            ..Default::default()
+        };
+
+        SyncSummary::new(&data, Some(&delta))
+    }
+
+    #[test]
+    fn summaries_name_subscribers_and_count_followers() {
+        let summary = summary();
+
+        assert_eq!(summary.new_subscribers, vec!["@fan", "2"]);
+        assert_eq!(summary.lost_subscribers, vec!["3", "4"]);
+        assert_eq!(summary.followers_line(), "120 (+2 / -1)");
+        assert_eq!(summary.subscribers_line(), "4 (3 active in this sync)");
+
+        // Nothing to compare against on the first sync
+        let first = SyncSummary::new(&SyncDataResponse::default(), None);
+        assert_eq!(first.followers_line(), "0");
+        assert!(first.new_subscribers.is_empty());
+        assert_eq!(first.tiers_text(), "None");
+    }
+
+    #[test]
+    fn long_lists_are_cut_short() {
+        assert_eq!(list_text(&[]), "None");
+
+        let names: Vec<String> = (1..=MAX_LISTED).map(|n| n.to_string()).collect();
+        assert_eq!(list_text(&names), "1, 2, 3, 4, 5, 6, 7, 8, 9, 10");
+
+        let names: Vec<String> = (1..=MAX_LISTED + 3).map(|n| n.to_string()).collect();
+        assert_eq!(
+            list_text(&names),
+            "1, 2, 3, 4, 5, 6, 7, 8, 9, 10 and 3 more"
+        );
+    }
+
+    #[test]
+    fn top_tiers_are_busiest_first_with_ties_by_name() {
+        let tier = |name: &str, active_subscribers| TierStats {
+            name: name.to_string(),
+            active_subscribers,
+            ..Default::default()
+        };
+        let data = SyncDataResponse {
+            tier_report: Some(TierReport {
+                total_active: 9,
+                tiers: vec![
+                    tier("Basic", 2),
+                    tier("Empty", 0),
+                    tier("VIP", 3),
+                    tier("Gold", 2),
+                    tier("Silver", 2),
+                ],
+            }),
+            ..Default::default()
+        };
+
+        let summary = SyncSummary::new(&data, None);
+        assert_eq!(
+            summary.top_tiers,
+            vec![
+                ("VIP".to_string(), 3),
+                ("Basic".to_string(), 2),
+                ("Gold".to_string(), 2)
+            ]
+        );
+        assert_eq!(summary.tiers_text(), "VIP: 3\nBasic: 2\nGold: 2");
+
+        // Without a tier report the synced subscriptions are counted by tier name
+        assert_eq!(
+            self::summary().top_tiers,
+            vec![("Basic".to_string(), 2), ("VIP".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn discord_and_slack_get_the_same_summary() {
+        let summary = summary();
+
+        let discord = discord_payload(&summary);
+        let embed = &discord["embeds"][0];
+        assert_eq!(embed["title"], TITLE);
+        assert_eq!(embed["color"], EMBED_COLOR);
+        let fields: Vec<(&str, &str)> = embed["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| {
+                (
+                    field["name"].as_str().unwrap(),
+                    field["value"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("Followers", "120 (+2 / -1)"),
+                ("Subscribers", "4 (3 active in this sync)"),
+                ("New Subscribers (2)", "@fan, 2"),
+                ("Lost Subscribers (2)", "3, 4"),
+                ("Top Tiers", "Basic: 2\nVIP: 1"),
+            ]
+        );
+
+        let slack = slack_payload(&summary);
+        assert_eq!(slack["text"], TITLE);
+        let blocks = slack["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len(), 5);
+        assert_eq!(blocks[0]["text"]["text"], TITLE);
+        assert_eq!(blocks[1]["fields"][0]["text"], "*Followers*\n120 (+2 / -1)");
+        assert_eq!(blocks[2]["text"]["text"], "*New Subscribers (2)*\n@fan, 2");
+        assert_eq!(blocks[3]["text"]["text"], "*Lost Subscribers (2)*\n3, 4");
+        assert_eq!(blocks[4]["text"]["text"], "*Top Tiers*\nBasic: 2\nVIP: 1");
+    }
+}
@@ -0,0 +1,559 @@
+// Config bundles for moving a setup to another machine. A bundle holds the settings, upload profile,
+// schedule and webhook destinations, but not the sync state of the machine it came from. Secrets are
+// either left out or encrypted with a passphrase, never written in the clear.
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
+use std::io;
+
+use super::{loaded, migrations, store, Config, CURRENT, CURRENT_VERSION};
+use crate::handlers::time::now_ms;
+use crate::handlers::webhooks::url_host;
+
+const FORMAT: &str = "fanslysync-config";
+const BUNDLE_VERSION: u32 = 1;
+// Fields that describe this machine's syncs rather than the setup
+const LOCAL_FIELDS: &[&str] = &["is_first_run", "last_sync", "last_sync_data"];
+const SECRET_FIELDS: &[&str] = &["fansly_token", "sync_token", "sync_signing_secret"];
+
+const KDF: &str = "pbkdf2-sha256";
+const KDF_ITERATIONS: u32 = 600_000;
+// Iteration counts we accept from a bundle. The count comes from the file, so it's capped to keep a
+// crafted bundle from tying up the app for hours.
+const MIN_KDF_ITERATIONS: u32 = 100_000;
+const MAX_KDF_ITERATIONS: u32 = 10_000_000;
+const MIN_PASSPHRASE_LENGTH: usize = 8;
+// Ties the ciphertext to this format, so it can't be passed off as something else
+const AAD: &[u8] = b"fanslysync-config-bundle-v1";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigBundle {
+    pub format: String,
+    pub bundle_version: u32,
+    pub exported_at: i64,
+    // The config as it was on disk, minus local state and with every secret blanked out
+    pub config: Value,
+    pub secrets: BundleSecrets,
+}
+
+// Secrets are keyed by their JSON pointer into `config`, e.g. "/webhooks/0/secret"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum BundleSecrets {
+    Omitted {
+        paths: Vec<String>,
+    },
+    Encrypted {
+        kdf: String,
+        iterations: u32,
+        salt: String,
+        nonce: String,
+        ciphertext: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    pub config: Config,
+    // Anything the user has to redo by hand, e.g. webhooks whose secrets weren't in the bundle
+    pub notes: Vec<String>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// Pointers to every secret in a raw config that is actually set. Chat webhook URLs
+// count as secrets, anyone holding one can post to the channel.
+pub(super) fn secret_pointers(config: &Value) -> Vec<String> {
+    let mut pointers: Vec<String> = SECRET_FIELDS
+        .iter()
+        .map(|field| format!("/{}", field))
+        .collect();
+
+    for (list, field) in [("webhooks", "secret"), ("chat_webhooks", "url")] {
+        let count = config[list].as_array().map_or(0, Vec::len);
+        pointers.extend((0..count).map(|index| format!("/{}/{}/{}", list, index, field)));
+    }
+
+    pointers.retain(|pointer| {
+        config
+            .pointer(pointer)
+            .and_then(Value::as_str)
+            .is_some_and(|secret| !secret.is_empty())
+    });
+    pointers
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> chacha20poly1305::Key {
+    let mut key = chacha20poly1305::Key::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+fn encrypt(secrets: &Map<String, Value>, passphrase: &str) -> io::Result<BundleSecrets> {
+    let salt: [u8; 16] = rand_bytes();
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt, KDF_ITERATIONS));
+
+    let plaintext = serde_json::to_vec(secrets)?;
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &plaintext,
+                aad: AAD,
+            },
+        )
+        .map_err(|_| io::Error::other("Failed to encrypt secrets"))?;
+
+    Ok(BundleSecrets::Encrypted {
+        kdf: KDF.to_string(),
+        iterations: KDF_ITERATIONS,
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+fn rand_bytes<const N: usize>() -> [u8; N] {
+    use chacha20poly1305::aead::rand_core::RngCore;
+
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn decrypt(
+    iterations: u32,
+    salt: &str,
+    nonce: &str,
+    ciphertext: &str,
+    passphrase: &str,
+) -> io::Result<Map<String, Value>> {
+    if !(MIN_KDF_ITERATIONS..=MAX_KDF_ITERATIONS).contains(&iterations) {
+        return Err(invalid(format!(
+            "Bundle asks for {} key derivation iterations, only {} to {} are supported",
+            iterations, MIN_KDF_ITERATIONS, MAX_KDF_ITERATIONS
+        )));
+    }
+
+    let decode = |field: &str, value: &str| {
+        BASE64
+            .decode(value)
+            .map_err(|_| invalid(format!("Bundle has an invalid {}", field)))
+    };
+    let salt = decode("salt", salt)?;
+    let nonce = decode("nonce", nonce)?;
+    let ciphertext = decode("ciphertext", ciphertext)?;
+
+    if nonce.len() != 24 {
+        return Err(invalid("Bundle has an invalid nonce"));
+    }
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt, iterations));
+    let plaintext = cipher
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: AAD,
+            },
+        )
+        .map_err(|_| {
+            invalid("Could not decrypt the bundle's secrets, the passphrase is wrong or the bundle was modified")
+        })?;
+
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+// Bundle up the config as it is on disk. Secrets are encrypted with `passphrase`, or left out without one.
+pub fn export_bundle(passphrase: Option<&str>) -> io::Result<ConfigBundle> {
+    if passphrase.is_some_and(|passphrase| passphrase.chars().count() < MIN_PASSPHRASE_LENGTH) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Passphrase must be at least {} characters",
+                MIN_PASSPHRASE_LENGTH
+            ),
+        ));
+    }
+
+    // Not current(), environment and command line overrides belong to this machine
+    let config = {
+        let mut current = CURRENT.lock().unwrap();
+        serde_json::to_value(&*loaded(&mut current)?)?
+    };
+
+    pack(config, passphrase)
+}
+
+// Strip local state from a raw config and split off its secrets
+fn pack(mut config: Value, passphrase: Option<&str>) -> io::Result<ConfigBundle> {
+    if let Some(fields) = config.as_object_mut() {
+        for field in LOCAL_FIELDS {
+            fields.remove(*field);
+        }
+    }
+
+    let mut secrets = Map::new();
+    for pointer in secret_pointers(&config) {
+        if let Some(secret) = config.pointer_mut(&pointer) {
+            secrets.insert(pointer, std::mem::replace(secret, json!("")));
+        }
+    }
+
+    let secrets = match passphrase {
+        Some(passphrase) => encrypt(&secrets, passphrase)?,
+        None => BundleSecrets::Omitted {
+            paths: secrets.keys().cloned().collect(),
+        },
+    };
+
+    log::info!(
+        "[config::export] Exported config bundle, secrets {}",
+        match secrets {
+            BundleSecrets::Omitted { .. } => "omitted",
+            BundleSecrets::Encrypted { .. } => "encrypted",
+        }
+    );
+
+    Ok(ConfigBundle {
+        format: FORMAT.to_string(),
+        bundle_version: BUNDLE_VERSION,
+        exported_at: now_ms(),
+        config,
+        secrets,
+    })
+}
+
+// Fill in what an export without secrets left blank. Tokens stay as they are on this machine,
+// webhooks that were signed are turned off and chat webhooks without a URL are dropped.
+fn fill_omitted(config: &mut Value, paths: &[String], stored: &Config, notes: &mut Vec<String>) {
+    let mut dropped_chat_webhooks = Vec::new();
+
+    for path in paths {
+        let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        match parts.as_slice() {
+            ["fansly_token"] => config["fansly_token"] = json!(stored.fansly_token),
+            ["sync_token"] => config["sync_token"] = json!(stored.sync_token),
+            ["sync_signing_secret"] => {
+                config["sync_signing_secret"] = json!(stored.sync_signing_secret)
+            }
+            ["webhooks", index, "secret"] => {
+                if let Some(hook) = index
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| config["webhooks"].get_mut(index))
+                {
+                    hook["enabled"] = json!(false);
+                    notes.push(format!(
+                        "Webhook to {} was disabled, its secret wasn't exported. Set the secret and enable it again.",
+                        url_host(hook["url"].as_str().unwrap_or_default())
+                    ));
+                }
+            }
+            ["chat_webhooks", index, "url"] => {
+                if let Ok(index) = index.parse::<usize>() {
+                    dropped_chat_webhooks.push(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(hooks) = config["chat_webhooks"].as_array_mut() {
+        dropped_chat_webhooks.sort_unstable();
+        dropped_chat_webhooks.dedup();
+        for index in dropped_chat_webhooks.into_iter().rev() {
+            if index < hooks.len() {
+                hooks.remove(index);
+                notes.push(
+                    "A chat webhook was skipped, its URL wasn't exported. Add it again in settings."
+                        .to_string(),
+                );
+            }
+        }
+    }
+}
+
+// Replace the config with the one in a bundle. It goes through the same migration and validation
+// as loading from disk, and this machine's sync state is kept.
+pub fn import_bundle(bundle: &str, passphrase: Option<&str>) -> io::Result<ImportSummary> {
+    let bundle = parse_bundle(bundle)?;
+
+    // Key derivation is slow on purpose, don't hold up everything else that needs the config meanwhile
+    let decrypted = decrypt_secrets(&bundle.secrets, passphrase)?;
+
+    let mut current = CURRENT.lock().unwrap();
+    let stored = loaded(&mut current)?.clone();
+
+    let (config, version, notes) = unpack(bundle, decrypted, &stored)?;
+    let config = store(&mut current, config)?;
+
+    log::info!(
+        "[config::import] Imported config bundle from version {} with {} note(s)",
+        version,
+        notes.len()
+    );
+
+    Ok(ImportSummary { config, notes })
+}
+
+fn parse_bundle(bundle: &str) -> io::Result<ConfigBundle> {
+    let bundle: ConfigBundle = serde_json::from_str(bundle)
+        .map_err(|e| invalid(format!("Not a FanslySync config bundle: {}", e)))?;
+
+    if bundle.format != FORMAT {
+        return Err(invalid("Not a FanslySync config bundle"));
+    }
+    if bundle.bundle_version > BUNDLE_VERSION {
+        return Err(invalid(
+            "This bundle was exported by a newer version of FanslySync. Please update FanslySync.",
+        ));
+    }
+
+    Ok(bundle)
+}
+
+// The bundle's secrets keyed by pointer, None when they were left out of it
+fn decrypt_secrets(
+    secrets: &BundleSecrets,
+    passphrase: Option<&str>,
+) -> io::Result<Option<Map<String, Value>>> {
+    let BundleSecrets::Encrypted {
+        kdf,
+        iterations,
+        salt,
+        nonce,
+        ciphertext,
+    } = secrets
+    else {
+        return Ok(None);
+    };
+
+    if kdf != KDF {
+        return Err(invalid(format!("Unsupported key derivation {}", kdf)));
+    }
+    let passphrase = passphrase.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "This bundle's secrets are encrypted, enter the passphrase it was exported with",
+        )
+    })?;
+
+    decrypt(*iterations, salt, nonce, ciphertext, passphrase).map(Some)
+}
+
+// Turn a bundle back into a config for this machine, along with the config version it came from
+// and notes for the user
+fn unpack(
+    bundle: ConfigBundle,
+    decrypted: Option<Map<String, Value>>,
+    stored: &Config,
+) -> io::Result<(Config, i64, Vec<String>)> {
+    let mut config = bundle.config;
+    let mut notes = Vec::new();
+
+    match (bundle.secrets, decrypted) {
+        (_, Some(secrets)) => {
+            for (pointer, secret) in secrets {
+                if secret.as_str().is_some_and(str::is_empty) {
+                    continue;
+                }
+                if let Some(field) = config.pointer_mut(&pointer) {
+                    *field = secret;
+                }
+            }
+        }
+        (BundleSecrets::Omitted { paths }, None) => {
+            fill_omitted(&mut config, &paths, stored, &mut notes)
+        }
+        (BundleSecrets::Encrypted { .. }, None) => {
+            return Err(invalid("The bundle's secrets were not decrypted"))
+        }
+    }
+
+    let version = migrations::version_of(&config)?;
+    let current_version = i64::from(CURRENT_VERSION);
+    if version > current_version {
+        return Err(invalid(format!(
+            "This bundle holds a config from a newer version of FanslySync (config version {}, this version supports up to {}). Please update FanslySync.",
+            version, current_version
+        )));
+    }
+
+    // Local state isn't part of the bundle, take it from this machine before deserializing.
+    // Tokens the exporting machine didn't have set don't wipe the ones set here either.
+    if let Some(fields) = config.as_object_mut() {
+        let stored_json = serde_json::to_value(stored)?;
+        for field in LOCAL_FIELDS {
+            fields.insert(field.to_string(), stored_json[*field].clone());
+        }
+        for field in SECRET_FIELDS {
+            if fields
+                .get(*field)
+                .and_then(Value::as_str)
+                .map_or(true, str::is_empty)
+            {
+                fields.insert(field.to_string(), stored_json[*field].clone());
+            }
+        }
+    }
+
+    let config = migrations::migrate(config, version, current_version)?;
+    Ok((Config::from_value(config)?, version, notes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::webhooks::WebhookConfig;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn config() -> Config {
+        Config {
+            fansly_token: "fansly token".to_string(),
+            sync_token: "sync token".to_string(),
+            sync_interval: 6,
+            last_sync: 1234,
+            webhooks: vec![WebhookConfig {
+                url: "https://example.com/hook".to_string(),
+                secret: "hook secret".to_string(),
+                enabled: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    // A bundle as it would be written to disk and read back
+    fn export(config: &Config, passphrase: Option<&str>) -> ConfigBundle {
+        let bundle = pack(serde_json::to_value(config).unwrap(), passphrase).unwrap();
+        parse_bundle(&serde_json::to_string(&bundle).unwrap()).unwrap()
+    }
+
+    fn import(
+        bundle: ConfigBundle,
+        passphrase: Option<&str>,
+        stored: &Config,
+    ) -> io::Result<(Config, Vec<String>)> {
+        let decrypted = decrypt_secrets(&bundle.secrets, passphrase)?;
+        unpack(bundle, decrypted, stored).map(|(config, _, notes)| (config, notes))
+    }
+
+    #[test]
+    fn round_trip_without_secrets() {
+        let bundle = export(&config(), None);
+        assert_eq!(bundle.config["webhooks"][0]["secret"], "");
+        assert!(bundle.config.get("last_sync").is_none());
+
+        let stored = Config {
+            fansly_token: "this machine".to_string(),
+            last_sync: 99,
+            ..Default::default()
+        };
+        let (imported, notes) = import(bundle, None, &stored).unwrap();
+
+        assert_eq!(imported.sync_interval, 6);
+        assert_eq!(imported.fansly_token, "this machine");
+        assert_eq!(imported.last_sync, 99);
+        assert!(!imported.webhooks[0].enabled);
+        assert_eq!(notes.len(), 1);
+    }
+
+    #[test]
+    fn round_trip_with_encrypted_secrets() {
+        let bundle = export(&config(), Some(PASSPHRASE));
+        assert!(!serde_json::to_string(&bundle)
+            .unwrap()
+            .contains("hook secret"));
+
+        let (imported, notes) = import(bundle, Some(PASSPHRASE), &Config::default()).unwrap();
+        assert_eq!(imported.fansly_token, "fansly token");
+        assert_eq!(imported.sync_token, "sync token");
+        assert_eq!(imported.webhooks[0].secret, "hook secret");
+        assert!(imported.webhooks[0].enabled);
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn unset_secrets_dont_wipe_local_ones() {
+        let exported = Config {
+            sync_token: String::new(),
+            ..config()
+        };
+        let stored = Config {
+            sync_token: "this machine".to_string(),
+            sync_signing_secret: "local secret".to_string(),
+            ..Default::default()
+        };
+
+        for passphrase in [None, Some(PASSPHRASE)] {
+            let bundle = export(&exported, passphrase);
+            let (imported, _) = import(bundle, passphrase, &stored).unwrap();
+            assert_eq!(imported.sync_token, "this machine");
+            assert_eq!(imported.sync_signing_secret, "local secret");
+        }
+
+        // Not even when a bundle carries one explicitly
+        let mut bundle = export(&config(), Some(PASSPHRASE));
+        let mut secrets = Map::new();
+        secrets.insert("/sync_token".to_string(), json!(""));
+        bundle.secrets = encrypt(&secrets, PASSPHRASE).unwrap();
+        let (imported, _) = import(bundle, Some(PASSPHRASE), &stored).unwrap();
+        assert_eq!(imported.sync_token, "this machine");
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let bundle = export(&config(), Some(PASSPHRASE));
+        assert!(import(bundle.clone(), Some("incorrect horse"), &Config::default()).is_err());
+        assert!(import(bundle, None, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn tampered_ciphertext_is_rejected() {
+        let mut bundle = export(&config(), Some(PASSPHRASE));
+        let BundleSecrets::Encrypted { ciphertext, .. } = &mut bundle.secrets else {
+            panic!("secrets should be encrypted");
+        };
+        let mut bytes = BASE64.decode(&*ciphertext).unwrap();
+        bytes[0] ^= 1;
+        *ciphertext = BASE64.encode(bytes);
+
+        assert!(import(bundle, Some(PASSPHRASE), &Config::default()).is_err());
+    }
+
+    #[test]
+    fn absurd_iteration_counts_are_rejected() {
+        for count in [0, 1, u32::MAX] {
+            let mut bundle = export(&config(), Some(PASSPHRASE));
+            let BundleSecrets::Encrypted { iterations, .. } = &mut bundle.secrets else {
+                panic!("secrets should be encrypted");
+            };
+            *iterations = count;
+
+            let error = import(bundle, Some(PASSPHRASE), &Config::default()).unwrap_err();
+            assert!(error.to_string().contains("iterations"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut bundle = export(&config(), None);
+        bundle.bundle_version = BUNDLE_VERSION + 1;
+        assert!(parse_bundle(&serde_json::to_string(&bundle).unwrap()).is_err());
+
+        let mut bundle = export(&config(), None);
+        bundle.config["version"] = json!(CURRENT_VERSION + 1);
+        let error = import(bundle, None, &Config::default()).unwrap_err();
+        assert!(error.to_string().contains("newer version"), "{}", error);
+    }
+}
@@ -0,0 +1,230 @@
+// Config migrations. Every step is a pure transform of the raw JSON from one version to the next,
+// so fields a step doesn't know about are carried over untouched.
+use serde_json::{json, Value};
+use std::io;
+
+pub struct Migration {
+    // The version this step migrates from, it produces `from + 1`
+    pub from: i64,
+    pub description: &'static str,
+    pub apply: fn(Value) -> Value,
+}
+
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    from: 1,
+    description: "Reset auto-sync, sync tokens from version 1 no longer work",
+    apply: v1_to_v2,
+}];
+
+fn v1_to_v2(mut config: Value) -> Value {
+    if let Some(fields) = config.as_object_mut() {
+        fields.insert("auto_sync_enabled".to_string(), json!(false));
+        fields.insert("sync_token".to_string(), json!(""));
+        fields.insert("sync_interval".to_string(), json!(1));
+
+        // Early version 1 configs could be missing these, version 2 requires them
+        fields.entry("is_first_run").or_insert_with(|| json!(true));
+        fields.entry("last_sync").or_insert_with(|| json!(0));
+        fields
+            .entry("last_sync_data")
+            .or_insert_with(|| json!({ "followers": [], "subscribers": [] }));
+    }
+    config
+}
+
+// The version a raw config says it is
+pub fn version_of(config: &Value) -> io::Result<i64> {
+    if !config.is_object() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Config file does not contain a JSON object",
+        ));
+    }
+
+    match config.get("version").and_then(Value::as_i64) {
+        Some(version) if version > 0 => Ok(version),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Config file has a missing or invalid version field",
+        )),
+    }
+}
+
+// Run every step between `from` and `to` in order
+pub fn migrate(mut config: Value, from: i64, to: i64) -> io::Result<Value> {
+    let mut version = from;
+
+    while version < to {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == version)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("No migration path for version {}", version),
+                )
+            })?;
+
+        log::info!(
+            "[config::migrate] Migrating config from version {} to {}: {}",
+            version,
+            version + 1,
+            migration.description
+        );
+
+        config = (migration.apply)(config);
+        version += 1;
+        config["version"] = json!(version);
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::config::{Config, CURRENT_VERSION};
+    use std::fs;
+    use std::path::PathBuf;
+
+    // One fixture per config version we have shipped, as a user would have it on disk.
+    // fixture() picks the first one listed for a version.
+    const FIXTURES: &[(i64, &str)] = &[
+        (1, include_str!("fixtures/v1.json")),
+        (1, include_str!("fixtures/v1-minimal.json")),
+        (2, include_str!("fixtures/v2.json")),
+    ];
+
+    fn fixture(version: i64) -> Value {
+        let (_, raw) = FIXTURES
+            .iter()
+            .find(|(fixture_version, _)| *fixture_version == version)
+            .expect("fixture exists");
+        serde_json::from_str(raw).unwrap()
+    }
+
+    fn temp_config(contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fanslysync-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn every_version_has_a_fixture_and_a_migration() {
+        for version in 1..=i64::from(CURRENT_VERSION) {
+            assert!(
+                FIXTURES.iter().any(|(v, _)| *v == version),
+                "missing fixture for version {}",
+                version
+            );
+        }
+        for version in 1..i64::from(CURRENT_VERSION) {
+            assert!(
+                MIGRATIONS.iter().any(|m| m.from == version),
+                "missing migration from version {}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn every_fixture_migrates_to_a_loadable_config() {
+        for (version, _) in FIXTURES {
+            let raw = fixture(*version);
+            assert_eq!(version_of(&raw).unwrap(), *version);
+
+            let migrated = migrate(raw, *version, i64::from(CURRENT_VERSION)).unwrap();
+            let config: Config = serde_json::from_value(migrated)
+                .unwrap_or_else(|e| panic!("version {} fixture: {}", version, e));
+
+            assert_eq!(config.version, CURRENT_VERSION);
+            assert_eq!(config.fansly_token, "fansly-token");
+        }
+    }
+
+    #[test]
+    fn v1_resets_auto_sync_and_keeps_everything_else() {
+        let migrated = migrate(fixture(1), 1, 2).unwrap();
+
+        assert_eq!(migrated["version"], json!(2));
+        assert_eq!(migrated["auto_sync_enabled"], json!(false));
+        assert_eq!(migrated["sync_token"], json!(""));
+        assert_eq!(migrated["sync_interval"], json!(1));
+
+        assert_eq!(migrated["is_first_run"], json!(false));
+        assert_eq!(migrated["last_sync"], json!(1721988883000_i64));
+        assert_eq!(
+            migrated["last_sync_data"]["followers"][0]["followerId"],
+            json!("123456")
+        );
+        assert_eq!(migrated["theme"], json!("dark"));
+    }
+
+    #[test]
+    fn minimal_v1_configs_get_defaults() {
+        let (_, raw) = FIXTURES[1];
+        let migrated = migrate(serde_json::from_str(raw).unwrap(), 1, 2).unwrap();
+
+        assert_eq!(migrated["is_first_run"], json!(true));
+        assert_eq!(migrated["last_sync"], json!(0));
+        assert_eq!(
+            migrated["last_sync_data"],
+            json!({ "followers": [], "subscribers": [] })
+        );
+
+        let config: Config = serde_json::from_value(migrated).unwrap();
+        assert_eq!(config.fansly_token, "fansly-token");
+        assert!(config.last_sync_data.subscribers.is_empty());
+    }
+
+    #[test]
+    fn unknown_fields_survive_a_round_trip() {
+        let config: Config = serde_json::from_value(fixture(2)).unwrap();
+        assert_eq!(config.extra.get("theme"), Some(&json!("dark")));
+
+        let saved = serde_json::to_value(&config).unwrap();
+        assert_eq!(saved["theme"], json!("dark"));
+    }
+
+    #[test]
+    fn missing_or_invalid_versions_are_rejected() {
+        assert!(version_of(&json!({})).is_err());
+        assert!(version_of(&json!({ "version": 0 })).is_err());
+        assert!(version_of(&json!({ "version": "2" })).is_err());
+        assert!(version_of(&json!([])).is_err());
+    }
+
+    #[test]
+    fn loading_an_old_config_migrates_it_and_keeps_a_backup() {
+        let (_, raw) = FIXTURES[0];
+        let path = temp_config(raw);
+
+        let config = Config::load_or_create(&path).unwrap();
+        assert_eq!(config.version, CURRENT_VERSION);
+        assert!(!config.auto_sync_enabled);
+
+        let backup = fs::read_to_string(path.with_extension("json.v1.bak")).unwrap();
+        assert_eq!(backup, raw);
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], json!(CURRENT_VERSION));
+        assert_eq!(saved["theme"], json!("dark"));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn configs_from_newer_versions_are_left_alone() {
+        let newer = json!({ "version": i64::from(CURRENT_VERSION) + 1, "fansly_token": "x" });
+        let raw = serde_json::to_string_pretty(&newer).unwrap();
+        let path = temp_config(&raw);
+
+        let error = Config::load_or_create(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read_to_string(&path).unwrap(), raw);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
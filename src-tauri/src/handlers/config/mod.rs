@@ -1,19 +1,41 @@
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use crate::structs::{FanslyFollowersResponse, Subscription};
+mod bundle;
+mod migrations;
+mod validation;
+
+pub use bundle::{export_bundle, import_bundle, ImportSummary};
+pub use validation::{describe_errors, FieldError, MAX_SYNC_INTERVAL_HOURS};
+
+use crate::handlers::chat::ChatWebhook;
+use crate::handlers::fansly::DEFAULT_SUBSCRIBER_STATUSES;
+use crate::handlers::overrides::overrides;
+use crate::handlers::redact;
+use crate::handlers::webhooks::{url_host, WebhookConfig};
+use crate::structs::{FanslyFollowersResponse, Subscription, SubscriptionStatus, UploadProfile};
 
 const CURRENT_VERSION: i32 = 2; // Set the current version of the config
+const BACKUP_COUNT: usize = 5; // How many previous configs we keep around
+
+lazy_static! {
+    // The config as we last loaded or wrote it. Every change goes through this lock,
+    // so two windows (or a window and the sync) can't overwrite each other's changes.
+    static ref CURRENT: Mutex<Option<Config>> = Mutex::new(None);
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncData {
     pub followers: Vec<FanslyFollowersResponse>,
     pub subscribers: Vec<Subscription>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub version: i32, // Add a version field to the config (1, 2, 3, etc.)
     pub is_first_run: bool,
@@ -23,6 +45,68 @@ pub struct Config {
     pub sync_interval: u64,
     pub last_sync: u64,
     pub last_sync_data: SyncData,
+    #[serde(default)]
+    pub upload_profile: UploadProfile,
+    #[serde(default)]
+    pub upload_field_allowlist: Vec<String>,
+    #[serde(default)]
+    pub sync_signing_secret: String,
+    #[serde(default = "default_subscriber_statuses")]
+    pub sync_subscriber_statuses: Vec<SubscriptionStatus>,
+    #[serde(default)]
+    pub sync_following: bool,
+    #[serde(default)]
+    pub sync_lists: bool,
+    #[serde(default)]
+    pub sync_enrich_accounts: bool,
+    #[serde(default)]
+    pub sync_earnings: bool,
+    #[serde(default = "default_earnings_days")]
+    pub sync_earnings_days: u32,
+    #[serde(default = "default_true")]
+    pub sync_tier_report: bool,
+    #[serde(default = "default_true")]
+    pub sync_history_enabled: bool,
+    #[serde(default = "default_history_limit")]
+    pub sync_history_limit: usize,
+    #[serde(default = "default_true")]
+    pub notify_new_subscribers: bool,
+    #[serde(default = "default_true")]
+    pub notify_tier_changes: bool,
+    #[serde(default = "default_true")]
+    pub notify_cancellations: bool,
+    #[serde(default = "default_true")]
+    pub notify_milestones: bool,
+    #[serde(default = "default_true")]
+    pub notify_sync_failures: bool,
+    #[serde(default)]
+    pub notify_digest: bool,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub chat_webhooks: Vec<ChatWebhook>,
+    // Also write structured JSON log lines to runtime.jsonl, takes effect on the next start
+    #[serde(default)]
+    pub log_json: bool,
+    // Fields this version doesn't know about (e.g. from a newer version), kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_history_limit() -> usize {
+    1000
+}
+
+fn default_earnings_days() -> u32 {
+    30
+}
+
+fn default_subscriber_statuses() -> Vec<SubscriptionStatus> {
+    DEFAULT_SUBSCRIBER_STATUSES.to_vec()
 }
 
 impl Default for Config {
@@ -39,147 +123,431 @@ impl Default for Config {
                 followers: Vec::new(),
                 subscribers: Vec::new(),
             }, // Last sync data is stored as a list of followers and subscribers
+            upload_profile: UploadProfile::Full, // Upload everything unless told otherwise
+            upload_field_allowlist: Vec::new(), // No extra field filtering by default
+            sync_signing_secret: String::new(), // Empty means uploads are signed with the sync token
+            sync_subscriber_statuses: default_subscriber_statuses(), // Active and expired subscribers
+            sync_following: false,                                   // Following list is opt-in
+            sync_lists: false,                                       // Custom lists are opt-in
+            sync_enrich_accounts: false, // Account lookups are opt-in, they cost extra requests
+            sync_earnings: false,        // Earnings are opt-in
+            sync_earnings_days: default_earnings_days(), // Last 30 days of earnings
+            sync_tier_report: true,      // The tier report is computed from data we already have
+            sync_history_enabled: true,  // Keep snapshots of past syncs for churn analytics
+            sync_history_limit: 1000, // Oldest snapshots are removed past this, 0 keeps all of them
+            notify_new_subscribers: true, // Desktop notifications after each sync
+            notify_tier_changes: true,
+            notify_cancellations: true,
+            notify_milestones: true,
+            notify_sync_failures: true,
+            notify_digest: false, // One notification per sync instead of one per event
+            webhooks: Vec::new(),
+            chat_webhooks: Vec::new(),
+            log_json: false,
+            extra: Map::new(),
         }
     }
 }
 
 impl Config {
     pub fn load_or_create(path: &Path) -> io::Result<Self> {
-        if path.exists() {
-            let config_result: Result<Self, _> =
-                serde_json::from_str(&std::fs::read_to_string(path)?);
-            let config = match config_result {
+        if !path.exists() {
+            let saved_config = Config::default().save(path);
+            return saved_config.and_then(|_| Config::load_or_create(path));
+        }
+
+        let config_raw = fs::read_to_string(path)?;
+        let (config_json, version) = match parse_raw(&config_raw) {
+            Ok(parsed) => parsed,
+            Err(e) => return Config::recover(path, e),
+        };
+        let current_version = i64::from(CURRENT_VERSION);
+
+        if version > current_version {
+            // Written by a newer FanslySync, we can't know what we'd break by rewriting it
+            log::error!(
+                "[config::load] Config file is version {}, this build only knows up to {}",
+                version,
+                current_version
+            );
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "This config file was written by a newer version of FanslySync (config version {}, this version supports up to {}). Please update FanslySync.",
+                    version, current_version
+                ),
+            ));
+        }
+
+        if version < current_version {
+            // Keep the original around in case a migration gets something wrong
+            let backup_path = path.with_extension(format!("json.v{}.bak", version));
+            fs::write(&backup_path, &config_raw)?;
+            log::info!(
+                "[config::migrate] Backed up version {} config to {}",
+                version,
+                backup_path.display()
+            );
+
+            let config = match Self::from_parsed(config_json, version) {
                 Ok(config) => config,
-                Err(_) => {
-                    // Load raw JSON and attempt to parse it as a JSON object
-                    let config_raw = std::fs::read_to_string(path)?;
-                    let config_json: serde_json::Value = serde_json::from_str(&config_raw)?;
-
-                    log::info!("[config::migrate] Migrating config file to latest version...");
-                    log::debug!(
-                        "[config::migrate] [DEBUG] config is_object: {}",
-                        config_json.is_object()
-                    );
-
-                    // Check if the JSON object is valid, if not, return an error
-                    if !config_json.is_object() {
-                        log::error!(
-                            "[config::migrate] [ERROR] Found invalid JSON object in config file"
-                        );
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "Tried to migrate a config file, but found an invalid JSON object",
-                        ));
-                    }
-
-                    // Get the version field from the JSON object
-                    let version = config_json["version"].as_i64().unwrap_or(0) as i32;
-
-                    // Check if the version field is a valid integer, if not, return an error
-                    if version == 0 {
-                        log::error!(
-                            "[config::migrate] [ERROR] Found invalid version field in config JSON"
-                        );
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "Tried to migrate a config file, but found an invalid version field",
-                        ));
-                    }
-
-                    log::info!(
-                        "[config::migrate] Found version field in config JSON: {}",
-                        version
-                    );
-
-                    // Now create a new Config object and set the version field to the value we found
-                    let mut config = Config::default();
-                    config.version = version;
-
-                    // Retain important fields from the JSON object
-                    config.is_first_run = config_json["is_first_run"].as_bool().unwrap_or(true);
-                    config.fansly_token = config_json["fansly_token"]
-                        .as_str()
-                        .unwrap_or("")
-                        .to_string();
-                    config.sync_token =
-                        config_json["sync_token"].as_str().unwrap_or("").to_string();
-                    config.sync_interval =
-                        config_json["sync_interval"].as_i64().unwrap_or(1) as u64;
-
-                    // Run migrations on the config object and save it
-                    config = config.migrate()?;
-                    config.save(path)?;
-
-                    log::info!(
-                        "[config::migrate] Successfully migrated config file to latest version"
-                    );
-                    // Recursively call load_or_create to load the migrated config
-                    return Config::load_or_create(path);
-                }
+                Err(e) => return Config::recover(path, e),
             };
+            config.save(path)?;
 
-            if config.version != CURRENT_VERSION {
-                // Should have been migrated by now, error out because it wasn't
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!(
-                        "Config version mismatch: expected {}, got {}. Please try removing the config file and restarting the application.", 
-                        CURRENT_VERSION, config.version
-                    ),
-                ));
-            }
+            log::info!("[config::migrate] Successfully migrated config file to latest version");
+            return Ok(config);
+        }
 
-            Ok(config)
-        } else {
-            let saved_config = Config::default().save(path);
-            saved_config
-                .and_then(|_| Config::load_or_create(path))
-                .or_else(|e| Err(e))
+        Self::from_parsed(config_json, version).or_else(|e| Config::recover(path, e))
+    }
+
+    // Migrate, deserialize and validate a parsed config. A config that fails any of these is
+    // treated as corrupt, same as one that isn't valid JSON.
+    fn from_parsed(config_json: Value, version: i64) -> io::Result<Self> {
+        let migrated = migrations::migrate(config_json, version, i64::from(CURRENT_VERSION))?;
+        Self::from_value(migrated)?.repaired().checked()
+    }
+
+    // Refuse to save a config that deserialized fine but holds values we can't work with.
+    // Overrides are never saved, so they can't make a save fail.
+    fn checked(self) -> io::Result<Self> {
+        let errors = self.validate();
+        if errors.is_empty() {
+            return Ok(self);
         }
+
+        log::error!(
+            "[config::validate] Config failed validation: {}",
+            describe_errors(&errors)
+        );
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid config: {}", describe_errors(&errors)),
+        ))
     }
 
-    fn migrate(mut self) -> io::Result<Self> {
-        while self.version < CURRENT_VERSION {
-            self = match self.version {
-                1 => {
-                    // Migrate from version 1 to version 2
-                    self.version = 2;
-                    self.auto_sync_enabled = false;
-                    self.sync_token = String::new();
-                    self.sync_interval = 1;
-
-                    self
-                }
-                _ => {
-                    // If we don't have a migration path, return an error
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("No migration path for version {}", self.version),
-                    ));
-                }
+    // The config as it can be shared with support, see redact_json
+    pub fn redacted(&self) -> io::Result<Value> {
+        let mut config_json = serde_json::to_value(self)?;
+        redact_json(&mut config_json);
+        Ok(config_json)
+    }
+
+    // Deserialize a raw config, pointing at the offending field if it doesn't fit
+    fn from_value(config: Value) -> io::Result<Self> {
+        serde_path_to_error::deserialize(config).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid config field {}: {}", e.path(), e.inner()),
+            )
+        })
+    }
+
+    // Put the newest backup that still loads back in place of a corrupt config file
+    fn recover(path: &Path, error: io::Error) -> io::Result<Self> {
+        log::error!("[config::recover] Config file is corrupt: {}", error);
+
+        for backup_path in backup_paths(path) {
+            let Ok(backup_raw) = fs::read_to_string(&backup_path) else {
+                continue;
             };
+            // Only restore a backup that loads all the way, or we'd end up back here
+            let usable = parse_raw(&backup_raw).is_ok_and(|(config_json, version)| {
+                version <= i64::from(CURRENT_VERSION)
+                    && Self::from_parsed(config_json, version).is_ok()
+            });
+            if !usable {
+                log::warn!(
+                    "[config::recover] Skipping corrupt backup {}",
+                    backup_path.display()
+                );
+                continue;
+            }
+
+            // Keep the broken file around for bug reports
+            fs::rename(path, path.with_extension("json.corrupt"))?;
+            write_atomic(path, backup_raw.as_bytes())?;
+
+            log::warn!(
+                "[config::recover] Restored config from backup {}",
+                backup_path.display()
+            );
+
+            // The backup may still need migrating
+            return Config::load_or_create(path);
         }
-        Ok(self)
+
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Config file is corrupt and no usable backup was found: {}",
+                error
+            ),
+        ))
     }
 
     pub fn save(&self, path: &Path) -> io::Result<()> {
-        let mut file = File::create(path)?;
-        file.write_all(serde_json::to_string_pretty(self).unwrap().as_bytes())?;
+        let contents = serde_json::to_string_pretty(self)?;
+
+        // Only rotate in a config that is worth restoring later
+        if fs::read_to_string(path).is_ok_and(|raw| parse_raw(&raw).is_ok()) {
+            rotate_backups(path)?;
+        }
+
+        write_atomic(path, contents.as_bytes())
+    }
+}
+
+// Strip a config down to what can be shared with support: secrets that are set show up as
+// "[redacted]", webhook URLs are reduced to their host and the last sync's data to counts.
+// Works on the raw JSON so it also covers config files that no longer load.
+pub fn redact_json(config_json: &mut Value) {
+    for pointer in bundle::secret_pointers(config_json) {
+        if let Some(secret) = config_json.pointer_mut(&pointer) {
+            *secret = Value::from("[redacted]");
+        }
+    }
+    redact::redact_value(config_json);
+
+    // Webhook URLs often carry credentials in the path or query string
+    for hook in config_json["webhooks"].as_array_mut().into_iter().flatten() {
+        if let Some(url) = hook["url"].as_str().filter(|url| !url.is_empty()) {
+            hook["url"] = Value::from(url_host(url));
+        }
+    }
 
-        // Return the saved config
-        Ok(())
+    // Subscriptions hold payment details, support only needs to know how much was synced
+    if let Some(last_sync_data) = config_json.get_mut("last_sync_data") {
+        let count = |list: &str| last_sync_data[list].as_array().map_or(0, Vec::len);
+        *last_sync_data = serde_json::json!({
+            "followers": count("followers"),
+            "subscribers": count("subscribers"),
+        });
     }
 }
 
+// A config that isn't a JSON object with a version is corrupt rather than just outdated
+fn parse_raw(raw: &str) -> io::Result<(Value, i64)> {
+    let config_json: Value = serde_json::from_str(raw)?;
+    let version = migrations::version_of(&config_json)?;
+    Ok((config_json, version))
+}
+
+// config.json.bak.1 (newest) through config.json.bak.<BACKUP_COUNT> (oldest)
+fn backup_paths(path: &Path) -> Vec<PathBuf> {
+    (1..=BACKUP_COUNT)
+        .map(|index| path.with_extension(format!("json.bak.{}", index)))
+        .collect()
+}
+
+fn rotate_backups(path: &Path) -> io::Result<()> {
+    let backups = backup_paths(path);
+
+    for index in (1..backups.len()).rev() {
+        if backups[index - 1].exists() {
+            fs::rename(&backups[index - 1], &backups[index])?;
+        }
+    }
+
+    fs::copy(path, &backups[0])?;
+    Ok(())
+}
+
+// Write to a temporary file next to the target, flush it to disk and rename it over the target,
+// so a crash or full disk leaves either the old or the new file but never half of one
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp_path = path.with_extension("json.tmp");
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_path, path)?;
+
+    // Make the rename itself durable. Directories can't be opened like this on Windows.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
 pub fn get_config_path() -> io::Result<PathBuf> {
-    let mut config_dir = dirs::config_dir().ok_or_else(|| {
-        io::Error::new(
-            io::ErrorKind::NotFound,
-            "Could not determine user's config directory",
+    Ok(overrides().data_dir()?.join("config.json"))
+}
+
+fn loaded(current: &mut Option<Config>) -> io::Result<&mut Config> {
+    if current.is_none() {
+        *current = Some(Config::load_or_create(&get_config_path()?)?);
+    }
+    Ok(current.as_mut().expect("config was just loaded"))
+}
+
+// The config as the rest of the app should see it, environment and command line overrides included
+fn effective(mut config: Config) -> Config {
+    overrides().apply(&mut config);
+    config
+}
+
+// The current config, read from disk the first time it's needed
+pub fn current() -> io::Result<Config> {
+    let mut current = CURRENT.lock().unwrap();
+    Ok(effective(loaded(&mut current)?.clone()))
+}
+
+// Validate and persist a whole config in place of the current one
+pub fn replace(mut config: Config) -> io::Result<Config> {
+    let mut current = CURRENT.lock().unwrap();
+
+    // The config most likely came from current(), don't write the overrides it carries to disk
+    overrides().keep_stored(&mut config, loaded(&mut current)?);
+
+    store(&mut current, config)
+}
+
+// Validate, persist and cache a config, with the lock on CURRENT held
+fn store(current: &mut Option<Config>, config: Config) -> io::Result<Config> {
+    let config = config.checked()?;
+    config.save(&get_config_path()?)?;
+    *current = Some(config.clone());
+
+    Ok(effective(config))
+}
+
+// Apply a JSON merge patch (RFC 7386) to the current config, then validate and persist the result
+pub fn update(patch: &Value) -> io::Result<Config> {
+    if !patch.is_object() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Config patch must be a JSON object",
+        ));
+    }
+
+    let mut current = CURRENT.lock().unwrap();
+
+    let mut config_json = serde_json::to_value(&*loaded(&mut current)?)?;
+    merge_patch(&mut config_json, patch);
+
+    store(&mut current, Config::from_value(config_json)?)
+}
+
+// Objects are merged key by key, null removes a key and anything else replaces the value outright
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!("target was just made an object");
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted_config_hides_secrets() {
+        let config = Config {
+            fansly_token: "fansly-token".to_string(),
+            webhooks: vec![
+                WebhookConfig {
+                    url: "https://example.com/hook?api_key=abc123&x=1".to_string(),
+                    secret: "hook-secret".to_string(),
+                    ..Default::default()
+                },
+                WebhookConfig {
+                    url: "https://example.com/plain".to_string(),
+                    ..Default::default()
+                },
+            ],
+            chat_webhooks: vec![ChatWebhook {
+                url: "https://hooks.slack.com/services/T0/B0/XXXX".to_string(),
+                ..Default::default()
+            }],
+            last_sync_data: SyncData {
+                followers: Vec::new(),
+                subscribers: vec![Subscription {
+                    payment_method_id: Some("card-123".to_string()),
+                    ..Default::default()
+                }],
+            },
+            ..Default::default()
+        };
+
+        let redacted = config.redacted().unwrap();
+        assert_eq!(
+            redacted["last_sync_data"],
+            serde_json::json!({ "followers": 0, "subscribers": 1 })
+        );
+        assert_eq!(redacted["fansly_token"], "[redacted]");
+        assert_eq!(redacted["webhooks"][0]["secret"], "[redacted]");
+        assert_eq!(redacted["webhooks"][0]["url"], "example.com");
+        assert_eq!(redacted["webhooks"][1]["url"], "example.com");
+        assert_eq!(redacted["chat_webhooks"][0]["url"], "[redacted]");
+
+        let text = redacted.to_string();
+        for secret in [
+            "fansly-token",
+            "abc123",
+            "hook-secret",
+            "XXXX",
+            "/plain",
+            "card-123",
+        ] {
+            assert!(!text.contains(secret), "{} leaked", secret);
+        }
+    }
+
+    #[test]
+    fn configs_that_dont_deserialize_are_restored_from_backup() {
+        let dir = std::env::temp_dir().join(format!("fanslysync-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+
+        let good = Config {
+            sync_interval: 6,
+            ..Default::default()
+        };
+        let mut broken = serde_json::to_value(&good).unwrap();
+        broken["sync_interval"] = Value::from("six");
+        fs::write(&path, broken.to_string()).unwrap();
+
+        // The newest backup has the wrong type as well, the older one is fine
+        fs::write(path.with_extension("json.bak.1"), broken.to_string()).unwrap();
+        fs::write(
+            path.with_extension("json.bak.2"),
+            serde_json::to_string(&good).unwrap(),
         )
-    })?;
-    config_dir.push("FanslySync");
-    fs::create_dir_all(&config_dir)?;
-    config_dir.push("config.json");
-    Ok(config_dir)
+        .unwrap();
+
+        let loaded = Config::load_or_create(&path).unwrap();
+        assert_eq!(loaded.sync_interval, 6);
+        assert!(path.with_extension("json.corrupt").exists());
+
+        // Without a usable backup the error is passed on
+        fs::write(&path, broken.to_string()).unwrap();
+        for backup in backup_paths(&path) {
+            let _ = fs::remove_file(backup);
+        }
+        let error = Config::load_or_create(&path).unwrap_err();
+        assert!(error.to_string().contains("sync_interval"), "{}", error);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,248 @@
+// Checks a config for values that would break syncing, reported per field so the settings form can point at them.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::{default_subscriber_statuses, Config, CURRENT_VERSION};
+
+// The frontend schedules syncs with setInterval, which gives up on delays over 2^31 - 1 ms
+// (about 24.8 days). Three weeks stays clear of that.
+pub const MAX_SYNC_INTERVAL_HOURS: u64 = 24 * 21;
+const MAX_EARNINGS_DAYS: u32 = 365;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    // The field the error is about, e.g. "sync_interval" or "webhooks[0].url"
+    pub path: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+// All errors on one line, for places that can only report a string
+pub fn describe_errors(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(FieldError::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn is_http_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+impl Config {
+    // Every problem with the config, empty when it's fine to save and use
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if self.version != CURRENT_VERSION {
+            errors.push(FieldError::new(
+                "version",
+                format!("must be {}", CURRENT_VERSION),
+            ));
+        }
+
+        if self.sync_interval == 0 || self.sync_interval > MAX_SYNC_INTERVAL_HOURS {
+            errors.push(FieldError::new(
+                "sync_interval",
+                format!("must be between 1 and {} hours", MAX_SYNC_INTERVAL_HOURS),
+            ));
+        }
+
+        if self.auto_sync_enabled {
+            if self.fansly_token.trim().is_empty() {
+                errors.push(FieldError::new(
+                    "fansly_token",
+                    "is required when auto-sync is enabled",
+                ));
+            }
+            if self.sync_token.trim().is_empty() {
+                errors.push(FieldError::new(
+                    "sync_token",
+                    "is required when auto-sync is enabled",
+                ));
+            }
+        }
+
+        if self.sync_subscriber_statuses.is_empty() {
+            errors.push(FieldError::new(
+                "sync_subscriber_statuses",
+                "must include at least one status",
+            ));
+        }
+
+        if self.sync_earnings_days == 0 || self.sync_earnings_days > MAX_EARNINGS_DAYS {
+            errors.push(FieldError::new(
+                "sync_earnings_days",
+                format!("must be between 1 and {} days", MAX_EARNINGS_DAYS),
+            ));
+        }
+
+        for (index, field) in self.upload_field_allowlist.iter().enumerate() {
+            if field.trim().is_empty() {
+                errors.push(FieldError::new(
+                    format!("upload_field_allowlist[{}]", index),
+                    "must not be empty",
+                ));
+            }
+        }
+
+        // Disabled hooks are never called, so they can wait to be fixed
+        for (index, hook) in self.webhooks.iter().enumerate() {
+            if hook.enabled && !is_http_url(&hook.url) {
+                errors.push(FieldError::new(
+                    format!("webhooks[{}].url", index),
+                    "must be an http or https URL",
+                ));
+            }
+        }
+
+        for (index, hook) in self.chat_webhooks.iter().enumerate() {
+            if hook.enabled && !is_http_url(&hook.url) {
+                errors.push(FieldError::new(
+                    format!("chat_webhooks[{}].url", index),
+                    "must be an http or https URL",
+                ));
+            }
+        }
+
+        errors
+    }
+
+    // Fix what validate() would complain about in a config read from disk, so a bad value
+    // written by an older version or by hand doesn't keep the app from starting
+    pub(super) fn repaired(mut self) -> Self {
+        let mut notes = Vec::new();
+
+        if self.version != CURRENT_VERSION {
+            notes.push(format!(
+                "version {} set to {}",
+                self.version, CURRENT_VERSION
+            ));
+            self.version = CURRENT_VERSION;
+        }
+
+        let interval = self.sync_interval.clamp(1, MAX_SYNC_INTERVAL_HOURS);
+        if interval != self.sync_interval {
+            notes.push(format!(
+                "sync_interval {} clamped to {}",
+                self.sync_interval, interval
+            ));
+            self.sync_interval = interval;
+        }
+
+        if self.auto_sync_enabled
+            && (self.fansly_token.trim().is_empty() || self.sync_token.trim().is_empty())
+        {
+            notes.push("auto-sync turned off, a token is missing".to_string());
+            self.auto_sync_enabled = false;
+        }
+
+        if self.sync_subscriber_statuses.is_empty() {
+            notes.push("sync_subscriber_statuses reset to the defaults".to_string());
+            self.sync_subscriber_statuses = default_subscriber_statuses();
+        }
+
+        let days = self.sync_earnings_days.clamp(1, MAX_EARNINGS_DAYS);
+        if days != self.sync_earnings_days {
+            notes.push(format!(
+                "sync_earnings_days {} clamped to {}",
+                self.sync_earnings_days, days
+            ));
+            self.sync_earnings_days = days;
+        }
+
+        let fields = self.upload_field_allowlist.len();
+        self.upload_field_allowlist
+            .retain(|field| !field.trim().is_empty());
+        if self.upload_field_allowlist.len() != fields {
+            notes.push("empty upload_field_allowlist entries removed".to_string());
+        }
+
+        for (index, hook) in self.webhooks.iter_mut().enumerate() {
+            if hook.enabled && !is_http_url(&hook.url) {
+                notes.push(format!("webhooks[{}] disabled, its URL is invalid", index));
+                hook.enabled = false;
+            }
+        }
+
+        for (index, hook) in self.chat_webhooks.iter_mut().enumerate() {
+            if hook.enabled && !is_http_url(&hook.url) {
+                notes.push(format!(
+                    "chat_webhooks[{}] disabled, its URL is invalid",
+                    index
+                ));
+                hook.enabled = false;
+            }
+        }
+
+        if !notes.is_empty() {
+            log::warn!(
+                "[config::repair] Repaired invalid config values: {}",
+                notes.join("; ")
+            );
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::webhooks::WebhookConfig;
+
+    #[test]
+    fn repairing_leaves_nothing_to_complain_about() {
+        let config = Config {
+            sync_interval: 0,
+            auto_sync_enabled: true,
+            sync_subscriber_statuses: Vec::new(),
+            sync_earnings_days: 10_000,
+            upload_field_allowlist: vec!["id".to_string(), " ".to_string()],
+            webhooks: vec![WebhookConfig {
+                url: "not a url".to_string(),
+                enabled: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(!config.validate().is_empty());
+
+        let repaired = config.repaired();
+        assert_eq!(repaired.validate(), Vec::new());
+        assert_eq!(repaired.sync_interval, 1);
+        assert!(!repaired.auto_sync_enabled);
+        assert_eq!(repaired.sync_earnings_days, MAX_EARNINGS_DAYS);
+        assert_eq!(repaired.upload_field_allowlist, vec!["id"]);
+        // Kept so the user can fix it
+        assert_eq!(repaired.webhooks[0].url, "not a url");
+        assert!(!repaired.webhooks[0].enabled);
+    }
+
+    #[test]
+    fn valid_configs_are_left_alone() {
+        let config = Config {
+            sync_interval: MAX_SYNC_INTERVAL_HOURS,
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(config.clone().repaired()).unwrap(),
+            serde_json::to_value(config).unwrap()
+        );
+    }
+}
@@ -0,0 +1,306 @@
+// What changed between two syncs, as individual events. Notifications and webhooks are built from this.
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::handlers::analytics::tiers::monthly_cents;
+use crate::handlers::history::{SubscriptionSnapshot, SyncSnapshot};
+use crate::structs::{AutoRenew, SubscriptionStatus};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriberEvent {
+    pub subscriber_id: String,
+    pub subscription_tier_id: String,
+    pub subscription_tier_name: String,
+}
+
+impl From<&SubscriptionSnapshot> for SubscriberEvent {
+    fn from(subscription: &SubscriptionSnapshot) -> Self {
+        Self {
+            subscriber_id: subscription.subscriber_id.clone(),
+            subscription_tier_id: subscription.subscription_tier_id.clone(),
+            subscription_tier_name: subscription.subscription_tier_name.clone(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TierChange {
+    pub subscriber_id: String,
+    pub from_tier_id: String,
+    pub from_tier_name: String,
+    pub to_tier_id: String,
+    pub to_tier_name: String,
+    // Whether the new tier costs more per month than the old one
+    pub upgrade: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Milestone {
+    pub previous: i64,
+    pub current: i64,
+    // The highest milestone that was crossed
+    pub reached: i64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncDelta {
+    pub new_subscribers: Vec<SubscriberEvent>,
+    pub cancellations: Vec<SubscriberEvent>,
+    pub expirations: Vec<SubscriberEvent>,
+    pub tier_changes: Vec<TierChange>,
+    pub new_followers: Vec<String>,
+    pub lost_followers: Vec<String>,
+    pub follower_milestone: Option<Milestone>,
+    pub subscriber_milestone: Option<Milestone>,
+}
+
+// 10, 25, 50, 100, 250, 500, 1000, ...
+fn milestones() -> impl Iterator<Item = i64> {
+    (1..18)
+        .map(|exponent| 10_i64.pow(exponent))
+        .flat_map(|base| [base, base * 5 / 2, base * 5])
+}
+
+fn milestone_between(previous: i64, current: i64) -> Option<Milestone> {
+    // A total of 0 means we don't know it (e.g. the previous sync came from the saved config)
+    if previous <= 0 || current <= previous {
+        return None;
+    }
+
+    milestones()
+        .take_while(|milestone| *milestone <= current)
+        .filter(|milestone| *milestone > previous)
+        .last()
+        .map(|reached| Milestone {
+            previous,
+            current,
+            reached,
+        })
+}
+
+impl SyncDelta {
+    pub fn between(previous: &SyncSnapshot, current: &SyncSnapshot) -> Self {
+        let mut delta = SyncDelta::default();
+
+        // With a different (or unknown) status filter, subscriptions come and go because of the filter
+        // rather than the subscriber, so only followers and totals can be compared
+        if previous.same_statuses_as(current) {
+            delta.compare_subscriptions(previous, current);
+        } else {
+            log::info!(
+                "[delta::between] Subscription statuses changed or unknown since the last sync ({:?} to {:?}), skipping subscriber events",
+                previous.statuses,
+                current.statuses
+            );
+        }
+
+        let followers_before: HashSet<&String> = previous.followers.iter().collect();
+        let followers_after: HashSet<&String> = current.followers.iter().collect();
+
+        delta.new_followers = current
+            .followers
+            .iter()
+            .filter(|follower| !followers_before.contains(follower))
+            .cloned()
+            .collect();
+        delta.lost_followers = previous
+            .followers
+            .iter()
+            .filter(|follower| !followers_after.contains(follower))
+            .cloned()
+            .collect();
+
+        delta.follower_milestone = milestone_between(previous.follow_count, current.follow_count);
+        delta.subscriber_milestone =
+            milestone_between(previous.subscriber_count, current.subscriber_count);
+
+        delta
+    }
+
+    // New subscribers, cancellations, expirations and tier changes
+    fn compare_subscriptions(&mut self, previous: &SyncSnapshot, current: &SyncSnapshot) {
+        let before = previous.by_subscriber();
+        let after = current.by_subscriber();
+
+        for (subscriber_id, now) in &after {
+            let was = before.get(subscriber_id);
+
+            if now.is_active() && !was.is_some_and(|was| was.is_active()) {
+                self.new_subscribers.push(SubscriberEvent::from(*now));
+            }
+
+            if let Some(was) = was {
+                // Cancelling on Fansly turns off auto-renew, the subscription stays active until it runs out
+                if was.is_active()
+                    && now.is_active()
+                    && was.auto_renew == AutoRenew::On
+                    && now.auto_renew == AutoRenew::Off
+                {
+                    self.cancellations.push(SubscriberEvent::from(*now));
+                }
+
+                if was.is_active()
+                    && now.is_active()
+                    && was.subscription_tier_id != now.subscription_tier_id
+                {
+                    self.tier_changes.push(TierChange {
+                        subscriber_id: subscriber_id.to_string(),
+                        from_tier_id: was.subscription_tier_id.clone(),
+                        from_tier_name: was.subscription_tier_name.clone(),
+                        to_tier_id: now.subscription_tier_id.clone(),
+                        to_tier_name: now.subscription_tier_name.clone(),
+                        upgrade: monthly_cents(&now.price, now.billing_cycle)
+                            > monthly_cents(&was.price, was.billing_cycle),
+                    });
+                }
+            }
+        }
+
+        // Gone from the sync or marked expired. Syncs that skip expired subscriptions just drop them.
+        for (subscriber_id, was) in &before {
+            let now = after.get(subscriber_id);
+            if was.status != SubscriptionStatus::Expired
+                && now.map_or(true, |now| now.status == SubscriptionStatus::Expired)
+            {
+                self.expirations.push(SubscriberEvent::from(*was));
+            }
+        }
+
+        // HashMap order is random, keep the events stable for notifications and webhooks
+        for events in [
+            &mut self.new_subscribers,
+            &mut self.cancellations,
+            &mut self.expirations,
+        ] {
+            events.sort_by(|a, b| a.subscriber_id.cmp(&b.subscriber_id));
+        }
+        self.tier_changes
+            .sort_by(|a, b| a.subscriber_id.cmp(&b.subscriber_id));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::handlers::fansly::DEFAULT_SUBSCRIBER_STATUSES;
+    use crate::structs::{BillingCycle, Money};
+
+    pub(crate) fn subscription(
+        subscriber_id: &str,
+        status: SubscriptionStatus,
+        auto_renew: AutoRenew,
+    ) -> SubscriptionSnapshot {
+        SubscriptionSnapshot {
+            id: format!("sub-{}", subscriber_id),
+            subscriber_id: subscriber_id.to_string(),
+            subscription_tier_id: "tier".to_string(),
+            subscription_tier_name: "Basic".to_string(),
+            status,
+            price: Money::from_cents(700),
+            auto_renew,
+            billing_cycle: BillingCycle::Monthly,
+            ..Default::default()
+        }
+    }
+
+    // What a sync with the default status filter returns: active and expired subscriptions
+    pub(crate) fn default_sync(
+        taken_at: i64,
+        subscriptions: Vec<SubscriptionSnapshot>,
+    ) -> SyncSnapshot {
+        assert!(subscriptions
+            .iter()
+            .all(|s| DEFAULT_SUBSCRIBER_STATUSES.contains(&s.status)));
+
+        SyncSnapshot {
+            taken_at,
+            subscriptions,
+            statuses: DEFAULT_SUBSCRIBER_STATUSES.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn turning_off_auto_renew_is_a_cancellation() {
+        use AutoRenew::{Off, On};
+        use SubscriptionStatus::Active;
+
+        let previous = default_sync(
+            1,
+            vec![
+                subscription("a", Active, On),
+                subscription("b", Active, On),
+                subscription("c", Active, Off),
+            ],
+        );
+        let current = default_sync(
+            2,
+            vec![
+                subscription("a", Active, Off),
+                subscription("b", Active, On),
+                subscription("c", Active, Off),
+                // Subscribed without auto-renew, nothing was cancelled
+                subscription("d", Active, Off),
+            ],
+        );
+
+        let delta = SyncDelta::between(&previous, &current);
+        let cancelled: Vec<&str> = delta
+            .cancellations
+            .iter()
+            .map(|event| event.subscriber_id.as_str())
+            .collect();
+
+        assert_eq!(cancelled, vec!["a"]);
+        assert!(delta.expirations.is_empty());
+        assert_eq!(delta.new_subscribers.len(), 1);
+    }
+
+    #[test]
+    fn running_out_is_an_expiration_not_a_cancellation() {
+        use AutoRenew::Off;
+        use SubscriptionStatus::{Active, Expired};
+
+        let previous = default_sync(1, vec![subscription("a", Active, Off)]);
+        let current = default_sync(2, vec![subscription("a", Expired, Off)]);
+
+        let delta = SyncDelta::between(&previous, &current);
+        assert!(delta.cancellations.is_empty());
+        assert_eq!(delta.expirations.len(), 1);
+    }
+
+    #[test]
+    fn a_changed_status_filter_doesnt_expire_everyone() {
+        use AutoRenew::On;
+        use SubscriptionStatus::{Active, Expired};
+
+        let previous = default_sync(
+            1,
+            vec![
+                subscription("a", Active, On),
+                subscription("b", Expired, On),
+            ],
+        );
+        let mut current = default_sync(2, vec![]);
+        current.statuses = vec![Expired];
+        current.followers = vec!["f".to_string()];
+
+        let delta = SyncDelta::between(&previous, &current);
+        assert!(delta.expirations.is_empty());
+        assert!(delta.new_subscribers.is_empty());
+        assert_eq!(delta.new_followers, vec!["f"]);
+
+        // Snapshots from before the filter was recorded, or the sync saved in the config, could have
+        // asked for anything
+        current.statuses = Vec::new();
+        let delta = SyncDelta::between(&previous, &current);
+        assert!(delta.expirations.is_empty());
+        let delta = SyncDelta::between(&current, &previous);
+        assert!(delta.expirations.is_empty());
+        assert!(delta.new_subscribers.is_empty());
+    }
+}
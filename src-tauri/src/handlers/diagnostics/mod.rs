@@ -0,0 +1,317 @@
+// Everything support needs to look into a failed sync, gathered into one zip file. Logs and config
+// are redacted before they go in, the zip is meant to be attached to a bug report as-is.
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::handlers::config::{self, get_config_path, Config};
+use crate::handlers::overrides::{overrides, Overrides};
+use crate::handlers::time::now_ms;
+use crate::handlers::{redact, webhooks};
+
+// Logs are written to <data dir>/runtime.log, see get_log_path in main.rs, and to
+// runtime.jsonl when JSON logging is on, see handlers::logging
+pub const LOG_FILE_STEM: &str = "runtime";
+const RECORDS_FILE: &str = "diagnostics.json";
+// How many sync runs and API errors we remember
+const MAX_RECORDS: usize = 20;
+const MAX_ERROR_BODY: usize = 2000;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncRun {
+    // Matches the run_id of the sync's structured log records
+    #[serde(default)]
+    pub run_id: String,
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub auto: bool,
+    pub success: bool,
+    pub followers: usize,
+    pub subscribers: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiError {
+    pub timestamp: i64,
+    pub endpoint: String,
+    pub status: Option<u16>,
+    // A redacted excerpt of the response body
+    pub body: String,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Records {
+    sync_runs: VecDeque<SyncRun>,
+    api_errors: VecDeque<ApiError>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemInfo {
+    pub app_version: String,
+    pub tauri_version: String,
+    pub os_type: String,
+    pub os_version: String,
+    pub os_family: String,
+    pub arch: String,
+    pub locale: Option<String>,
+}
+
+lazy_static! {
+    // Loaded from disk on first use, so a bundle made after a restart still shows what went wrong before it
+    static ref RECORDS: Mutex<Option<Records>> = Mutex::new(None);
+}
+
+fn records_path() -> io::Result<PathBuf> {
+    Ok(overrides().data_dir()?.join(RECORDS_FILE))
+}
+
+fn load_records() -> Records {
+    records_path()
+        .and_then(fs::read_to_string)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn push<T>(list: &mut VecDeque<T>, item: T) {
+    if list.len() >= MAX_RECORDS {
+        list.pop_front();
+    }
+    list.push_back(item);
+}
+
+// Change the records and write them back. Failing to keep diagnostics must never fail a sync.
+fn with_records(change: impl FnOnce(&mut Records)) {
+    let mut records = RECORDS.lock().unwrap();
+    let records = records.get_or_insert_with(load_records);
+    change(records);
+
+    let saved = records_path().and_then(|path| {
+        let contents = serde_json::to_string(&*records)?;
+        fs::write(path, contents)
+    });
+    if let Err(e) = saved {
+        log::warn!("[diagnostics] Failed to save diagnostics records: {}", e);
+    }
+}
+
+pub fn record_sync_run(mut run: SyncRun) {
+    run.error = run.error.map(|error| redact::redact_text(&error, &[]));
+    with_records(|records| push(&mut records.sync_runs, run));
+}
+
+// Remember a failed API response. The body is redacted here, callers pass it as received.
+pub fn record_api_error(endpoint: &str, status: Option<u16>, body: &str) {
+    let error = ApiError {
+        timestamp: now_ms(),
+        endpoint: endpoint.to_string(),
+        status,
+        body: redact::body_snippet(body, MAX_ERROR_BODY),
+    };
+    with_records(|records| push(&mut records.api_errors, error));
+}
+
+// Every secret we know of, so log lines mentioning them can be cleaned up whatever their format
+fn known_secrets() -> Vec<String> {
+    secrets_in(config::current().ok(), overrides())
+}
+
+fn secrets_in(config: Option<Config>, overrides: &Overrides) -> Vec<String> {
+    let mut secrets = Vec::new();
+
+    if let Some(config) = config {
+        secrets.extend([
+            config.fansly_token,
+            config.sync_token,
+            config.sync_signing_secret,
+        ]);
+        secrets.extend(config.webhooks.into_iter().map(|hook| hook.secret));
+        secrets.extend(config.chat_webhooks.into_iter().map(|hook| hook.url));
+    }
+
+    secrets.extend(overrides.fansly_token.clone());
+    secrets.extend(overrides.sync_token.clone());
+
+    secrets.retain(|secret| !secret.is_empty());
+    secrets
+}
+
+fn log_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            name.starts_with(LOG_FILE_STEM) && (name.ends_with(".log") || name.ends_with(".jsonl"))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// The redacted config, or the redacted raw file when it can't be loaded (which may be why we're here)
+fn config_entry() -> Value {
+    match config::current().and_then(|config| config.redacted()) {
+        Ok(config) => config,
+        Err(e) => {
+            let raw = get_config_path()
+                .and_then(fs::read_to_string)
+                .map(|raw| redacted_raw_config(&raw))
+                .unwrap_or_else(|e| json!(format!("<could not read config file: {}>", e)));
+            json!({ "loadError": e.to_string(), "raw": raw })
+        }
+    }
+}
+
+// A config file that doesn't load, redacted the same way as a loaded one. Without valid JSON
+// we can't tell where the subscriptions and webhook URLs are, so only its size goes in.
+fn redacted_raw_config(raw: &str) -> Value {
+    match serde_json::from_str::<Value>(raw) {
+        Ok(mut config_json) => {
+            config::redact_json(&mut config_json);
+            config_json
+        }
+        Err(_) => json!(format!("<{} bytes of non-JSON config>", raw.len())),
+    }
+}
+
+// Write the diagnostics zip to `path`
+pub fn create_bundle(path: &Path, system: &SystemInfo) -> io::Result<()> {
+    let records = {
+        let mut records = RECORDS.lock().unwrap();
+        records.get_or_insert_with(load_records).clone()
+    };
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default();
+
+    let mut add_json = |name: &str, value: &Value| -> io::Result<()> {
+        zip.start_file(name, options)?;
+        zip.write_all(serde_json::to_string_pretty(value)?.as_bytes())
+    };
+
+    add_json(
+        "system.json",
+        &json!({
+            "createdAt": now_ms(),
+            "system": system,
+            "overrides": overrides().active(),
+        }),
+    )?;
+    add_json("config.json", &config_entry())?;
+    add_json("sync_runs.json", &json!(records.sync_runs))?;
+    add_json("api_errors.json", &json!(records.api_errors))?;
+    add_json("webhook_deliveries.json", &json!(webhooks::deliveries()))?;
+
+    let secrets = known_secrets();
+    for log_path in log_files(&overrides().data_dir()?)? {
+        let Some(name) = log_path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+
+        // Logs may contain invalid UTF-8 if a write was cut short
+        let contents = String::from_utf8_lossy(&fs::read(&log_path)?).into_owned();
+        zip.start_file(format!("logs/{}", name), options)?;
+        zip.write_all(redact::redact_text(&contents, &secrets).as_bytes())?;
+    }
+
+    zip.finish()?;
+
+    log::info!(
+        "[diagnostics::create_bundle] Wrote diagnostics bundle to {}",
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::chat::ChatWebhook;
+    use crate::handlers::webhooks::WebhookConfig;
+
+    #[test]
+    fn known_secrets_cover_config_and_overrides() {
+        let config = Config {
+            fansly_token: "fansly-token".to_string(),
+            sync_token: "sync-token".to_string(),
+            webhooks: vec![
+                WebhookConfig {
+                    secret: "hook-secret".to_string(),
+                    ..Default::default()
+                },
+                WebhookConfig::default(),
+            ],
+            chat_webhooks: vec![ChatWebhook {
+                url: "https://discord.com/api/webhooks/1/abc".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let overrides = Overrides {
+            sync_token: Some("env-sync-token".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            secrets_in(Some(config), &overrides),
+            vec![
+                "fansly-token",
+                "sync-token",
+                "hook-secret",
+                "https://discord.com/api/webhooks/1/abc",
+                "env-sync-token",
+            ]
+        );
+        assert!(secrets_in(None, &Overrides::default()).is_empty());
+    }
+
+    #[test]
+    fn raw_configs_are_redacted() {
+        // Wrong field types, so this wouldn't load as a Config
+        let raw = json!({
+            "version": 2,
+            "sync_interval": "six",
+            "sync_token": "sync-token",
+            "webhooks": [{ "url": "https://example.com/hooks/abc123", "secret": "hook-secret" }],
+            "chat_webhooks": [{ "url": "https://discord.com/api/webhooks/1/xyz" }],
+            "last_sync_data": {
+                "followers": [{ "followerId": "1" }],
+                "subscribers": [{ "id": "2", "paymentMethodId": "card-123" }]
+            }
+        })
+        .to_string();
+
+        let redacted = redacted_raw_config(&raw);
+        assert_eq!(redacted["sync_interval"], "six");
+        assert_eq!(redacted["webhooks"][0]["url"], "example.com");
+        assert_eq!(
+            redacted["last_sync_data"],
+            json!({ "followers": 1, "subscribers": 1 })
+        );
+
+        let text = redacted.to_string();
+        for secret in ["sync-token", "abc123", "hook-secret", "xyz", "card-123"] {
+            assert!(!text.contains(secret), "{} leaked", secret);
+        }
+
+        assert_eq!(
+            redacted_raw_config("{\"sync_token\": \"sync-tok"),
+            "<24 bytes of non-JSON config>"
+        );
+    }
+}
@@ -1,15 +1,251 @@
 // Create a simple module for handling the Fansly API, using reqwest to make requests to the API.
 // This module will contain a struct Fansly, which will have a method to get the user's profile information.
+use crate::handlers::analytics::tiers;
+use crate::handlers::config::Config;
+use crate::handlers::diagnostics;
+use crate::handlers::payload::PayloadFilter;
+use crate::handlers::redact;
+use crate::handlers::scheduler;
+use crate::handlers::signing::{self, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use crate::handlers::time::now_ms;
 use crate::structs::{
-    FanslyAccountResponse, FanslyBaseResponse, FanslyBaseResponseList, FanslyFollowersResponse,
-    FanslySubscriptionsResponse, Subscription, SyncDataResponse,
+    AccountSummary, EarningsData, EarningsSummary, FanslyAccountLookup, FanslyAccountResponse,
+    FanslyBaseResponse, FanslyBaseResponseList, FanslyFollowersResponse, FanslyFollowingResponse,
+    FanslyList, FanslyListItem, FanslySubscriptionsResponse, MainWallet, Subscription,
+    SubscriptionStatus, SyncDataResponse, SyncTokenInfo, WalletTransaction,
 };
 use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+// What we last learned about the Fansly token
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenStatus {
+    #[default]
+    Unknown,
+    Valid,
+    Invalid,
+}
+
+#[derive(Debug)]
+pub enum FanslyError {
+    // Fansly rejected our token (401/403), the user needs to re-enter it
+    Unauthorized(StatusCode),
+    Http(reqwest::Error),
+    // Fansly sent something we couldn't decode, `field` is the path to the offending field
+    Decode {
+        endpoint: String,
+        field: String,
+        message: String,
+    },
+    Other(String),
+}
+
+impl fmt::Display for FanslyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FanslyError::Unauthorized(status) => write!(
+                f,
+                "Fansly rejected your token ({}). Please re-enter your Fansly token.",
+                status
+            ),
+            FanslyError::Http(e) => write!(f, "{}", e),
+            FanslyError::Decode {
+                endpoint,
+                field,
+                message,
+            } => write!(
+                f,
+                "Could not read Fansly's response from {} (field `{}`): {}",
+                endpoint, field, message
+            ),
+            FanslyError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// Only failures from Fansly's own API say anything about the Fansly token, see failed_fansly_response
+impl From<reqwest::Error> for FanslyError {
+    fn from(e: reqwest::Error) -> Self {
+        FanslyError::Http(e)
+    }
+}
+
+fn is_auth_failure(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+// Send a request inside a `request` span carrying the endpoint, page offset, status code and duration,
+// so each event of a sync run can be tied to the request it came from
+async fn send(
+    request: reqwest::RequestBuilder,
+    endpoint: &str,
+    offset: Option<usize>,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let span = tracing::info_span!(
+        "request",
+        endpoint,
+        offset,
+        status = tracing::field::Empty,
+        duration_ms = tracing::field::Empty,
+    );
+
+    async {
+        let started = Instant::now();
+        let result = request.send().await;
+
+        let span = tracing::Span::current();
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        match &result {
+            Ok(response) => {
+                span.record("status", response.status().as_u16());
+                tracing::info!("Request finished");
+            }
+            Err(e) => tracing::warn!(error = %e, "Request failed"),
+        }
+
+        result
+    }
+    .instrument(span)
+    .await
+}
+
+// Mark which part of the sync we're in, events from here on carry it
+fn enter_phase(phase: &'static str) {
+    tracing::Span::current().record("phase", phase);
+    tracing::info!(phase, "Sync phase started");
+}
+
+// Keep a failed response around for diagnostics bundles and turn it into an error
+async fn failed_response(response: reqwest::Response, endpoint: &str) -> reqwest::Error {
+    let error = response.error_for_status_ref().unwrap_err();
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default();
+    diagnostics::record_api_error(endpoint, Some(status), &body);
+    error
+}
+
+// Like failed_response, for apiv3.fansly.com. A 401 or 403 from there means Fansly rejected our token.
+async fn failed_fansly_response(response: reqwest::Response, endpoint: &str) -> FanslyError {
+    let status = response.status();
+    let error = failed_response(response, endpoint).await;
+    if is_auth_failure(status) {
+        FanslyError::Unauthorized(status)
+    } else {
+        FanslyError::Http(error)
+    }
+}
+
+// Decode a response body, reporting exactly which field broke if Fansly changed its shape
+async fn decode_response<T: DeserializeOwned>(
+    response: reqwest::Response,
+    endpoint: &str,
+) -> Result<T, FanslyError> {
+    let body = response.text().await?;
+    let deserializer = &mut serde_json::Deserializer::from_str(&body);
+
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let field = e.path().to_string();
+        let message = e.inner().to_string();
+
+        tracing::error!(
+            endpoint,
+            field = %field,
+            error = %message,
+            body = %redact::body_snippet(&body, 500),
+            "Failed to decode response"
+        );
+
+        diagnostics::record_api_error(endpoint, None, &body);
+
+        FanslyError::Decode {
+            endpoint: endpoint.to_string(),
+            field,
+            message,
+        }
+    })
+}
+
+// The subscription statuses we ask Fansly for unless told otherwise
+pub const DEFAULT_SUBSCRIBER_STATUSES: [SubscriptionStatus; 2] =
+    [SubscriptionStatus::Active, SubscriptionStatus::Expired];
+
+// Fansly returns at most this many items per page
+const PAGE_SIZE: usize = 100;
+
+// How many account ids we look up per request, and how long we trust a looked up account
+const ACCOUNT_LOOKUP_BATCH: usize = 50;
+const ACCOUNT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// A hung webhook or chat endpoint shouldn't hold on to its delivery task forever
+const EXTERNAL_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const EXTERNAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Everything that changes what a sync fetches and uploads
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncOptions {
+    pub upload_filter: PayloadFilter,
+    pub subscriber_statuses: Vec<SubscriptionStatus>,
+    pub include_following: bool,
+    pub include_lists: bool,
+    pub enrich_accounts: bool,
+    // Earnings are fetched for the last `earnings_days` days
+    pub include_earnings: bool,
+    pub earnings_days: u32,
+    pub include_tier_report: bool,
+}
+
+impl SyncOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            upload_filter: PayloadFilter::from_config(config),
+            subscriber_statuses: config.sync_subscriber_statuses.clone(),
+            include_following: config.sync_following,
+            include_lists: config.sync_lists,
+            enrich_accounts: config.sync_enrich_accounts,
+            include_earnings: config.sync_earnings,
+            earnings_days: config.sync_earnings_days,
+            include_tier_report: config.sync_tier_report,
+        }
+    }
+
+    // The statuses to request, falling back to the defaults if none were picked
+    pub fn statuses(&self) -> Vec<SubscriptionStatus> {
+        let mut statuses = if self.subscriber_statuses.is_empty() {
+            DEFAULT_SUBSCRIBER_STATUSES.to_vec()
+        } else {
+            self.subscriber_statuses.clone()
+        };
+
+        statuses.sort_by_key(|status| i64::from(*status));
+        statuses.dedup();
+        statuses
+    }
+
+    fn statuses_query(&self) -> String {
+        self.statuses()
+            .iter()
+            .map(|status| i64::from(*status).to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
 
 pub struct Fansly {
     client: reqwest::Client,
+    // Same user agent but without the Fansly token, for requests to anything that isn't Fansly
+    external_client: reqwest::Client,
     token: Option<String>,
+    token_status: TokenStatus,
+    // Looked up accounts, kept across syncs so we only ask Fansly about new or stale ids
+    account_cache: Mutex<HashMap<String, (AccountSummary, Instant)>>,
 }
 
 impl Fansly {
@@ -24,24 +260,43 @@ impl Fansly {
 
         // If we have a token, add it to the headers\
         if let Some(token) = &token {
-            headers.insert(
-                "Authorization",
-                HeaderValue::from_str(&format!("{}", token)).unwrap(),
-            );
+            headers.insert("Authorization", HeaderValue::from_str(token).unwrap());
         }
 
+        let external_client = reqwest::Client::builder()
+            .user_agent("FanslySync/0.1.0 tanner@fanslycreatorbot.com")
+            .connect_timeout(EXTERNAL_CONNECT_TIMEOUT)
+            .timeout(EXTERNAL_TIMEOUT)
+            .build()
+            .unwrap();
+
         // Set our default base url to https://apiv3.fansly.com/api/v1/
         let client = reqwest::Client::builder()
             .default_headers(headers)
             .build()
             .unwrap();
 
-        Self { client, token }
+        Self {
+            client,
+            external_client,
+            token,
+            token_status: TokenStatus::Unknown,
+            account_cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // A client for webhooks and other third party endpoints, never carries the Fansly token
+    pub fn external_client(&self) -> reqwest::Client {
+        self.external_client.clone()
     }
 
     // Helper function to set our token on the fly
     pub fn set_token(&mut self, token: Option<String>) {
         self.token = token;
+        self.token_status = TokenStatus::Unknown;
+
+        // Another account may see other details (or none at all), start over
+        self.account_cache.lock().unwrap().clear();
 
         // Re-create the client with the new token (if it exists)
         let mut headers = HeaderMap::new();
@@ -53,10 +308,7 @@ impl Fansly {
 
         // If we have a token, add it to the headers
         if let Some(token) = &self.token {
-            headers.insert(
-                "Authorization",
-                HeaderValue::from_str(&format!("{}", token)).unwrap(),
-            );
+            headers.insert("Authorization", HeaderValue::from_str(token).unwrap());
         }
 
         self.client = reqwest::Client::builder()
@@ -65,26 +317,72 @@ impl Fansly {
             .unwrap();
     }
 
+    pub fn token_status(&self) -> TokenStatus {
+        self.token_status
+    }
+
+    pub fn mark_token_invalid(&mut self) {
+        self.token_status = TokenStatus::Invalid;
+    }
+
+    // Ask Fansly whether our token still works, without treating a rejection as an error
+    pub async fn validate_token(&mut self) -> Result<TokenStatus, reqwest::Error> {
+        if self.token.is_none() {
+            self.token_status = TokenStatus::Invalid;
+            return Ok(self.token_status);
+        }
+
+        let response = send(
+            self.client
+                .get("https://apiv3.fansly.com/api/v1/account/me"),
+            "account/me",
+            None,
+        )
+        .await?;
+
+        let status = response.status();
+        if is_auth_failure(status) {
+            tracing::warn!(
+                endpoint = "account/me",
+                status = status.as_u16(),
+                "Fansly rejected our token"
+            );
+            self.token_status = TokenStatus::Invalid;
+        } else if status.is_success() {
+            self.token_status = TokenStatus::Valid;
+        } else {
+            tracing::error!(
+                endpoint = "account/me",
+                status = status.as_u16(),
+                "Unexpected status while validating token"
+            );
+            return Err(failed_response(response, "account/me").await);
+        }
+
+        Ok(self.token_status)
+    }
+
     pub async fn get_profile(
         &self,
-    ) -> Result<FanslyBaseResponse<FanslyAccountResponse>, reqwest::Error> {
-        let response = self
-            .client
-            .get("https://apiv3.fansly.com/api/v1/account/me")
-            .send()
-            .await?;
+    ) -> Result<FanslyBaseResponse<FanslyAccountResponse>, FanslyError> {
+        let response = send(
+            self.client
+                .get("https://apiv3.fansly.com/api/v1/account/me"),
+            "account/me",
+            None,
+        )
+        .await?;
 
         if !response.status().is_success() {
-            eprintln!("[sync::process::get_profile] No successful response from API. Setting error state.");
-            return Err(response.error_for_status().unwrap_err());
-        } else {
-            println!("[sync::process::get_profile] Got successful response from API.");
+            tracing::error!(
+                endpoint = "account/me",
+                status = response.status().as_u16(),
+                "No successful response from API"
+            );
+            return Err(failed_fansly_response(response, "account/me").await);
         }
 
-        let profile = response
-            .json::<FanslyBaseResponse<FanslyAccountResponse>>()
-            .await?;
-        Ok(profile)
+        decode_response(response, "account/me").await
     }
 
     async fn fetch_followers(
@@ -92,14 +390,11 @@ impl Fansly {
         account_id: &str,
         auth_token: &str,
         offset: u32,
-    ) -> Result<FanslyBaseResponseList<FanslyFollowersResponse>, reqwest::Error> {
+    ) -> Result<FanslyBaseResponseList<FanslyFollowersResponse>, FanslyError> {
         let url = format!("https://apiv3.fansly.com/api/v1/account/{}/followers?ngsw-bypass=true&limit=100&offset={}", account_id, offset);
 
         let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::AUTHORIZATION,
-            format!("{}", auth_token).parse().unwrap(),
-        );
+        headers.insert(reqwest::header::AUTHORIZATION, auth_token.parse().unwrap());
         headers.insert(
             reqwest::header::USER_AGENT,
             "FanslySync/1.0.0 (tanner@fanslycreatorbot.com)"
@@ -112,34 +407,163 @@ impl Fansly {
             "application/json".parse().unwrap(),
         );
 
-        let response = self.client.get(url).headers(headers).send().await?;
+        let response = send(
+            self.client.get(url).headers(headers),
+            "account/followers",
+            Some(offset as usize),
+        )
+        .await?;
 
         if !response.status().is_success() {
-            eprintln!("[sync::process::fetch_followers] No successful response from API. Setting error state.");
-            return Err(response.error_for_status().unwrap_err());
+            tracing::error!(
+                endpoint = "account/followers",
+                status = response.status().as_u16(),
+                "No successful response from API"
+            );
+            return Err(failed_fansly_response(response, "account/followers").await);
         }
 
-        let followers: FanslyBaseResponseList<FanslyFollowersResponse> = response.json().await?;
-        println!(
-            "[sync::process::fetch_followers] Got {} followers from API.",
-            followers.response.len()
+        decode_response(response, "account/followers").await
+    }
+
+    // GET a Fansly endpoint with our default (authorized) headers and decode the reply
+    async fn get_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        endpoint: &str,
+        offset: Option<usize>,
+    ) -> Result<T, FanslyError> {
+        let response = send(self.client.get(url), endpoint, offset).await?;
+
+        if !response.status().is_success() {
+            tracing::error!(
+                endpoint,
+                status = response.status().as_u16(),
+                "No successful response from API"
+            );
+            return Err(failed_fansly_response(response, endpoint).await);
+        }
+
+        decode_response(response, endpoint).await
+    }
+
+    async fn fetch_following(
+        &self,
+        account_id: &str,
+        offset: usize,
+    ) -> Result<Vec<FanslyFollowingResponse>, FanslyError> {
+        let url = format!("https://apiv3.fansly.com/api/v1/account/{}/following?ngsw-bypass=true&limit={}&offset={}", account_id, PAGE_SIZE, offset);
+
+        let following: FanslyBaseResponseList<FanslyFollowingResponse> = self
+            .get_json(&url, "account/following", Some(offset))
+            .await?;
+
+        tracing::info!(
+            endpoint = "account/following",
+            count = following.response.len(),
+            "Fetched followed accounts"
+        );
+
+        Ok(following.response)
+    }
+
+    async fn fetch_lists(&self) -> Result<Vec<FanslyList>, FanslyError> {
+        let url = "https://apiv3.fansly.com/api/v1/lists/account?ngsw-bypass=true";
+
+        let lists: FanslyBaseResponseList<FanslyList> =
+            self.get_json(url, "lists/account", None).await?;
+
+        tracing::info!(
+            endpoint = "lists/account",
+            count = lists.response.len(),
+            "Fetched lists"
         );
 
-        Ok(followers)
+        Ok(lists.response)
+    }
+
+    async fn fetch_list_members(
+        &self,
+        list_id: &str,
+        offset: usize,
+    ) -> Result<Vec<FanslyListItem>, FanslyError> {
+        let url = format!(
+            "https://apiv3.fansly.com/api/v1/lists/items?listId={}&ngsw-bypass=true&limit={}&offset={}",
+            list_id, PAGE_SIZE, offset
+        );
+
+        let items: FanslyBaseResponseList<FanslyListItem> =
+            self.get_json(&url, "lists/items", Some(offset)).await?;
+
+        Ok(items.response)
+    }
+
+    async fn fetch_wallets(&self) -> Result<Vec<MainWallet>, FanslyError> {
+        let url = "https://apiv3.fansly.com/api/v1/account/wallets?ngsw-bypass=true";
+
+        let wallets: FanslyBaseResponseList<MainWallet> =
+            self.get_json(url, "account/wallets", None).await?;
+
+        Ok(wallets.response)
+    }
+
+    // Transactions between `start` and `end` (UNIX milliseconds)
+    async fn fetch_transactions(
+        &self,
+        start: i64,
+        end: i64,
+        offset: usize,
+    ) -> Result<Vec<WalletTransaction>, FanslyError> {
+        let url = format!(
+            "https://apiv3.fansly.com/api/v1/account/wallets/transactions?after={}&before={}&ngsw-bypass=true&limit={}&offset={}",
+            start, end, PAGE_SIZE, offset
+        );
+
+        let transactions: FanslyBaseResponseList<WalletTransaction> = self
+            .get_json(&url, "account/wallets/transactions", Some(offset))
+            .await?;
+
+        tracing::info!(
+            endpoint = "account/wallets/transactions",
+            count = transactions.response.len(),
+            "Fetched transactions"
+        );
+
+        Ok(transactions.response)
+    }
+
+    // Wallet balances plus every transaction between `start` and `end` (UNIX milliseconds)
+    pub async fn get_earnings(&self, start: i64, end: i64) -> Result<EarningsData, FanslyError> {
+        let mut total_requests = 0;
+        self.sync_earnings(start, end, &mut total_requests).await
+    }
+
+    async fn fetch_accounts(&self, ids: &[String]) -> Result<Vec<AccountSummary>, FanslyError> {
+        let url = format!(
+            "https://apiv3.fansly.com/api/v1/account?ids={}&ngsw-bypass=true",
+            ids.join(",")
+        );
+
+        let accounts: FanslyBaseResponseList<FanslyAccountLookup> =
+            self.get_json(&url, "account", None).await?;
+
+        Ok(accounts
+            .response
+            .into_iter()
+            .map(AccountSummary::from)
+            .collect())
     }
 
     async fn fetch_subscribers(
         &self,
         auth_token: &str,
+        statuses: &str,
         offset: u32,
-    ) -> Result<Vec<Subscription>, reqwest::Error> {
-        let url = format!("https://apiv3.fansly.com/api/v1/subscribers?status=3,4&limit=100&offset={}&ngsw-bypass=true", offset);
+    ) -> Result<Vec<Subscription>, FanslyError> {
+        let url = format!("https://apiv3.fansly.com/api/v1/subscribers?status={}&limit=100&offset={}&ngsw-bypass=true", statuses, offset);
 
         let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::AUTHORIZATION,
-            format!("{}", auth_token).parse().unwrap(),
-        );
+        headers.insert(reqwest::header::AUTHORIZATION, auth_token.parse().unwrap());
         headers.insert(
             reqwest::header::USER_AGENT,
             "FanslySync/1.0.0 (tanner@fanslycreatorbot.com)"
@@ -151,56 +575,69 @@ impl Fansly {
             "application/json".parse().unwrap(),
         );
 
-        let response = self.client.get(url).headers(headers).send().await?;
+        let response = send(
+            self.client.get(url).headers(headers),
+            "subscribers",
+            Some(offset as usize),
+        )
+        .await?;
 
         if !response.status().is_success() {
-            log::error!("[sync::process::fetch_subscribers] No successful response from API. Setting error state.");
-            let error = response.error_for_status().unwrap_err();
-            return Err(error);
+            tracing::error!(
+                endpoint = "subscribers",
+                status = response.status().as_u16(),
+                "No successful response from API"
+            );
+            return Err(failed_fansly_response(response, "subscribers").await);
         }
 
         let subscriptions: FanslyBaseResponse<FanslySubscriptionsResponse> =
-            response.json().await?;
+            decode_response(response, "subscribers").await?;
 
-        log::info!(
-            "[sync::process::fetch_subscribers] Got {} subscribers from API.",
-            subscriptions.response.subscriptions.len()
+        tracing::info!(
+            endpoint = "subscribers",
+            count = subscriptions.response.subscriptions.len(),
+            "Fetched subscribers"
         );
 
         Ok(subscriptions.response.subscriptions)
     }
 
-    async fn upload_sync_data(&self, data: SyncDataResponse) -> Result<String, reqwest::Error> {
+    async fn upload_sync_data(
+        &self,
+        data: &SyncDataResponse,
+        filter: &PayloadFilter,
+    ) -> Result<String, FanslyError> {
         let url = "https://paste.fanslycreatorbot.com";
 
-        // Convert passed data to bytes
-        let json_string = serde_json::to_string(&data).unwrap();
+        // Strip anything the upload profile doesn't allow, then convert to bytes
+        let payload = filter
+            .apply(data)
+            .map_err(|e| FanslyError::Other(e.to_string()))?;
+        let json_string = payload.to_string();
         let data_as_bytes = json_string.as_bytes();
 
-        let form = reqwest::multipart::Form::new()
-            .part(
-                "file",
-                reqwest::multipart::Part::bytes(data_as_bytes.to_vec())
-                    .file_name("sync_data.json")
-                    .mime_str("application/json")?,
-            );
+        let form = reqwest::multipart::Form::new().part(
+            "file",
+            reqwest::multipart::Part::bytes(data_as_bytes.to_vec())
+                .file_name("sync_data.json")
+                .mime_str("application/json")?,
+        );
 
         // Create a new client and POST
-        let response = self
-            .client
-            .post(url)
-            .multipart(form)
-            .send()
-            .await?;
+        let response = send(self.client.post(url).multipart(form), "upload", None).await?;
 
         if !response.status().is_success() {
-            log::error!("Failed to upload sync data...");
-            log::info!("Response: {:?}", response);
-            return Err(response.error_for_status().unwrap_err());
+            tracing::error!(
+                endpoint = "upload",
+                status = response.status().as_u16(),
+                "Failed to upload sync data"
+            );
+            return Err(failed_response(response, "upload").await.into());
         }
 
         let reply = response.text().await?;
-        log::info!("Uploaded sync data successfully. Response: {}", reply);
+        tracing::info!(endpoint = "upload", url = %reply, "Uploaded sync data");
         Ok(reply)
     }
 
@@ -208,9 +645,22 @@ impl Fansly {
         &self,
         data: SyncDataResponse,
         token: String,
-    ) -> Result<(), reqwest::Error> {
+        filter: &PayloadFilter,
+        signing_secret: Option<&str>,
+    ) -> Result<(), FanslyError> {
         let url = "https://botapi.fanslycreatorbot.com/sync";
 
+        // Never upload one account's data with another account's sync token
+        self.verify_sync_token_account(&token).await?;
+
+        // Only share the fields the upload profile allows
+        let payload = filter
+            .apply(&data)
+            .map_err(|e| FanslyError::Other(e.to_string()))?;
+
+        // Sign the body so the bot can verify it came from us (falls back to the sync token as the key)
+        let signed = signing::sign_payload(signing_secret.unwrap_or(&token), &payload);
+
         // Set our content type to application/json
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
@@ -219,27 +669,61 @@ impl Fansly {
         );
 
         // Add our auth token to the headers
-        headers.insert("Authorization", format!("{}", token).parse().unwrap());
-
-        let response = self
-            .client
-            .post(url)
-            .headers(headers)
-            .json(&data)
-            .send()
-            .await?;
+        headers.insert("Authorization", token.parse().unwrap());
+
+        // Add the signature headers
+        headers.insert(SIGNATURE_HEADER, signed.signature.parse().unwrap());
+        headers.insert(
+            TIMESTAMP_HEADER,
+            signed.timestamp.to_string().parse().unwrap(),
+        );
+        headers.insert(NONCE_HEADER, signed.nonce.parse().unwrap());
+
+        let response = send(
+            self.client.post(url).headers(headers).body(signed.body),
+            "upload_auto_sync_data",
+            None,
+        )
+        .await?;
 
         if !response.status().is_success() {
-            log::error!("Failed to upload sync data...");
-            log::info!("Response: {:?}", response);
-            return Err(response.error_for_status().unwrap_err());
+            tracing::error!(
+                endpoint = "upload_auto_sync_data",
+                status = response.status().as_u16(),
+                "Failed to upload sync data"
+            );
+            return Err(failed_response(response, "upload_auto_sync_data")
+                .await
+                .into());
         }
 
-        log::info!("Uploaded sync data successfully.");
+        tracing::info!(endpoint = "upload_auto_sync_data", "Uploaded sync data");
         Ok(())
     }
 
-    pub async fn check_sync_token(&self, token: String) -> Result<Value, reqwest::Error> {
+    // Check that the sync token belongs to the Fansly account we are logged in as
+    pub async fn verify_sync_token_account(
+        &self,
+        token: &str,
+    ) -> Result<SyncTokenInfo, FanslyError> {
+        let token_info = self
+            .check_sync_token(token.to_string())
+            .await
+            .map_err(|e| FanslyError::Other(format!("Could not validate sync token: {}", e)))?;
+
+        // Unauthorized is passed on as is, callers handle a revoked Fansly token
+        let profile = self.get_profile().await?;
+        // Auto-sync can't fix this by retrying, it stays paused until the token changes
+        if let Err(e) = token_info.ensure_linked_to(&profile.response.account) {
+            tracing::error!(error = %e, "Sync token belongs to another account");
+            scheduler::pause(e.clone());
+            return Err(FanslyError::Other(e));
+        }
+
+        Ok(token_info)
+    }
+
+    pub async fn check_sync_token(&self, token: String) -> Result<SyncTokenInfo, FanslyError> {
         // Check if the token is valid (GET /checkSyncToken with Authorization header)
         // If it is, return the data back from the API
         // If it isn't, return an error
@@ -253,132 +737,340 @@ impl Fansly {
         );
 
         // Add our auth token to the headers
-        headers.insert("Authorization", format!("{}", token).parse().unwrap());
+        headers.insert("Authorization", token.parse().unwrap());
 
-        let response = self.client.get(url).headers(headers).send().await;
+        let response = send(
+            self.client.get(url).headers(headers),
+            "checkSyncToken",
+            None,
+        )
+        .await;
 
         // If successful, return the data, otherwise return an error
-        match response {
-            Ok(response) => {
-                if !response.status().is_success() {
-                    log::error!("Failed to check sync token...");
-                    log::info!("Response: {:?}", response);
-                    return Err(response.error_for_status().unwrap_err());
-                }
-
-                let json: serde_json::Value = response.json().await?;
-                Ok(json)
-            }
-            Err(e) => Err(e),
+        let response = response?;
+        if !response.status().is_success() {
+            tracing::error!(
+                endpoint = "checkSyncToken",
+                status = response.status().as_u16(),
+                "Failed to check sync token"
+            );
+            let status = response.status();
+            let error = failed_response(response, "checkSyncToken").await;
+            // The bot API turning down the sync token says nothing about the Fansly token
+            return Err(if is_auth_failure(status) {
+                FanslyError::Other(format!(
+                    "The sync token was rejected ({}). Please check your sync token.",
+                    status
+                ))
+            } else {
+                FanslyError::Http(error)
+            });
         }
+
+        let json: Value = response.json().await?;
+        SyncTokenInfo::from_value(json.clone()).map_err(|e| {
+            tracing::error!(endpoint = "checkSyncToken", error = %e, "Unexpected sync token response");
+            diagnostics::record_api_error("checkSyncToken", None, &json.to_string());
+            FanslyError::Other(e)
+        })
     }
 
-    pub async fn sync(&self, auto: bool) -> Result<SyncDataResponse, String> {
-        // Fetch profile
-        log::info!("[sync::process] Fetching profile...");
-        let profile = self.get_profile().await.map_err(|e| e.to_string())?;
+    #[tracing::instrument(name = "sync_process", skip_all, fields(auto = auto, phase = tracing::field::Empty))]
+    pub async fn sync(
+        &self,
+        auto: bool,
+        options: &SyncOptions,
+    ) -> Result<SyncDataResponse, FanslyError> {
+        enter_phase("profile");
+        let profile = self.get_profile().await?;
 
         if !profile.success {
-            return Err("Failed to fetch profile".to_string());
+            return Err(FanslyError::Other("Failed to fetch profile".to_string()));
         }
 
-        log::info!("[sync::process] Syncing profile...");
-
         let account = profile.response.account;
         let total_followers = account.follow_count;
         let total_subscribers = account.subscriber_count;
 
-        log::info!("[sync::process] Account ID: {}, Followers: {}, Subscribers: {}",
-            account.id, total_followers, total_subscribers);
+        tracing::info!(
+            account_id = %account.id,
+            followers = total_followers,
+            subscribers = total_subscribers,
+            "Fetched profile"
+        );
 
         let mut followers: Vec<FanslyFollowersResponse> = Vec::new();
         let mut subscribers: Vec<Subscription> = Vec::new();
 
-        log::info!("[sync::process] Fetching followers...");
+        enter_phase("followers");
 
         // Fetch followers until we have all of them
         let mut offset = 0;
         let mut total_requests = 0;
         while followers.len() < total_followers as usize {
-            log::info!(
-                "[sync::process] Fetching followers for account {} with offset {} (total: {})",
-                account.id, offset, total_followers
-            );
             let response = self
-                .fetch_followers(&account.id, &self.token.as_ref().unwrap(), offset)
-                .await
-                .map_err(|e| e.to_string())?;
-
-            log::info!(
-                "[sync::process] Got {} followers from API.",
-                response.response.len()
+                .fetch_followers(&account.id, self.token.as_ref().unwrap(), offset)
+                .await?;
+
+            tracing::info!(
+                offset,
+                count = response.response.len(),
+                total = total_followers,
+                "Fetched followers page"
             );
             followers.extend(response.response);
             offset += 100;
-            total_requests += 1;
-
-            // Every 10 requests, sleep for a bit to avoid rate limiting
-            if total_requests % 10 == 0 {
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-            }
+            Self::throttle(&mut total_requests).await;
         }
 
-        // Fetch subscribers until we have all of them
+        // Fetch subscribers until we run out of pages. The status filter means we can't rely on
+        // the profile's subscriber count, it only counts active subscribers.
+        enter_phase("subscribers");
+        let statuses = options.statuses_query();
         offset = 0;
-        while subscribers.len() < total_subscribers as usize {
-            log::info!(
-                "[sync::process] Fetching subscribers with offset {} for account {} (total: {})",
-                offset, account.id, total_subscribers
-            );
-
+        loop {
             let response = self
-                .fetch_subscribers(&self.token.as_ref().unwrap(), offset)
-                .await
-                .map_err(|e| e.to_string())?;
-
+                .fetch_subscribers(self.token.as_ref().unwrap(), &statuses, offset)
+                .await?;
+
+            let page_len = response.len();
+            tracing::info!(
+                offset,
+                count = page_len,
+                statuses = %statuses,
+                "Fetched subscribers page"
+            );
             subscribers.extend(response);
             offset += 100;
-            total_requests += 1;
+            Self::throttle(&mut total_requests).await;
 
-            // Every 10 requests, sleep for a bit to avoid rate limiting
-            if total_requests % 10 == 0 {
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            if page_len < PAGE_SIZE {
+                break;
             }
         }
 
-        log::info!(
-            "[sync::process] Got {} followers and {} subscribers from API.",
-            followers.len(),
-            subscribers.len()
+        tracing::info!(
+            followers = followers.len(),
+            subscribers = subscribers.len(),
+            "Fetched followers and subscribers"
         );
 
-        log::info!("[sync::process] Sync complete.");
-        log::info!("[sync::process] Uploading sync data to paste.hep.gg for processing...");
+        // Optional sections, only fetched when asked for
+        let following = if options.include_following {
+            enter_phase("following");
+            Some(
+                self.sync_following(&account.id, &mut total_requests)
+                    .await?,
+            )
+        } else {
+            None
+        };
+
+        let lists = if options.include_lists {
+            enter_phase("lists");
+            Some(self.sync_lists(&mut total_requests).await?)
+        } else {
+            None
+        };
+
+        let accounts = if options.enrich_accounts {
+            enter_phase("accounts");
+            let ids = followers
+                .iter()
+                .map(|follower| follower.follower_id.clone())
+                .chain(subscribers.iter().map(|sub| sub.subscriber_id.clone()))
+                .collect::<Vec<_>>();
+
+            Some(self.resolve_accounts(ids, &mut total_requests).await?)
+        } else {
+            None
+        };
+
+        let earnings = if options.include_earnings {
+            enter_phase("earnings");
+            let end = now_ms();
+            let start = end - i64::from(options.earnings_days) * 24 * 60 * 60 * 1000;
+            Some(self.sync_earnings(start, end, &mut total_requests).await?)
+        } else {
+            None
+        };
+
+        let tier_report = options
+            .include_tier_report
+            .then(|| tiers::tier_report(&account, &subscribers));
+
+        tracing::info!("Sync complete");
+
+        let mut data = SyncDataResponse {
+            followers,
+            subscribers,
+            following,
+            lists,
+            accounts,
+            earnings,
+            tier_report,
+            follow_count: total_followers,
+            subscriber_count: total_subscribers,
+            sync_data_url: "".to_string(),
+        };
 
         // Upload sync data to paste.hep.gg
         if !auto {
-            let paste_url = self
-                .upload_sync_data(SyncDataResponse {
-                    followers: followers.clone(),
-                    subscribers: subscribers.clone(),
-                    sync_data_url: "".to_string(),
-                })
-                .await
-                .map_err(|e| e.to_string())?;
-
-            // Return JSON of what we fetched
-            Ok(SyncDataResponse {
-                followers,
-                subscribers,
-                sync_data_url: paste_url,
-            })
-        } else {
-            // Return JSON of what we fetched
-            Ok(SyncDataResponse {
-                followers,
-                subscribers,
-                sync_data_url: "".to_string(),
-            })
+            enter_phase("upload");
+            data.sync_data_url = self.upload_sync_data(&data, &options.upload_filter).await?;
+        }
+
+        // Return JSON of what we fetched
+        Ok(data)
+    }
+
+    // Every 10 requests, sleep for a bit to avoid rate limiting
+    async fn throttle(total_requests: &mut u32) {
+        *total_requests += 1;
+        if *total_requests % 10 == 0 {
+            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        }
+    }
+
+    async fn sync_following(
+        &self,
+        account_id: &str,
+        total_requests: &mut u32,
+    ) -> Result<Vec<FanslyFollowingResponse>, FanslyError> {
+        let mut following = Vec::new();
+
+        loop {
+            let offset = following.len();
+            let page = self.fetch_following(account_id, offset).await?;
+            let page_len = page.len();
+            tracing::info!(offset, count = page_len, "Fetched following page");
+            following.extend(page);
+            Self::throttle(total_requests).await;
+
+            if page_len < PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(following)
+    }
+
+    // Look up account details for the given ids, using the cache where we can
+    async fn resolve_accounts(
+        &self,
+        mut ids: Vec<String>,
+        total_requests: &mut u32,
+    ) -> Result<BTreeMap<String, AccountSummary>, FanslyError> {
+        ids.sort();
+        ids.dedup();
+
+        let mut accounts = BTreeMap::new();
+        let mut missing = Vec::new();
+        {
+            let cache = self.account_cache.lock().unwrap();
+            for id in ids {
+                match cache.get(&id) {
+                    Some((account, fetched_at)) if fetched_at.elapsed() < ACCOUNT_CACHE_TTL => {
+                        accounts.insert(id, account.clone());
+                    }
+                    _ => missing.push(id),
+                }
+            }
+        }
+
+        tracing::info!(
+            cached = accounts.len(),
+            missing = missing.len(),
+            "Resolving accounts"
+        );
+
+        for batch in missing.chunks(ACCOUNT_LOOKUP_BATCH) {
+            let fetched = self.fetch_accounts(batch).await?;
+            Self::throttle(total_requests).await;
+
+            let mut cache = self.account_cache.lock().unwrap();
+            for account in fetched {
+                cache.insert(account.id.clone(), (account.clone(), Instant::now()));
+                accounts.insert(account.id.clone(), account);
+            }
+        }
+
+        Ok(accounts)
+    }
+
+    async fn sync_earnings(
+        &self,
+        start: i64,
+        end: i64,
+        total_requests: &mut u32,
+    ) -> Result<EarningsData, FanslyError> {
+        tracing::info!(start, end, "Fetching wallets and transactions");
+
+        let wallets = self.fetch_wallets().await?;
+        Self::throttle(total_requests).await;
+
+        let mut transactions = Vec::new();
+        loop {
+            let page = self
+                .fetch_transactions(start, end, transactions.len())
+                .await?;
+            let page_len = page.len();
+            transactions.extend(page);
+            Self::throttle(total_requests).await;
+
+            if page_len < PAGE_SIZE {
+                break;
+            }
         }
+
+        let summary = EarningsSummary::from_transactions(start, end, &wallets, &transactions);
+        if summary.total.is_none() {
+            tracing::warn!(
+                currencies = ?summary.balances.keys().collect::<Vec<_>>(),
+                "Wallets are in more than one currency, earnings are not totalled"
+            );
+        }
+        tracing::info!(
+            wallets = wallets.len(),
+            transactions = summary.transaction_count,
+            total = ?summary.total.map(|total| total.to_string()),
+            "Fetched earnings"
+        );
+
+        Ok(EarningsData {
+            wallets,
+            summary,
+            transactions,
+        })
+    }
+
+    async fn sync_lists(&self, total_requests: &mut u32) -> Result<Vec<FanslyList>, FanslyError> {
+        let mut lists = self.fetch_lists().await?;
+        tracing::info!(lists = lists.len(), "Fetched lists");
+        Self::throttle(total_requests).await;
+
+        for list in lists.iter_mut() {
+            let mut offset = 0;
+            loop {
+                let page = self.fetch_list_members(&list.id, offset).await?;
+                let page_len = page.len();
+                tracing::info!(
+                    list_id = %list.id,
+                    offset,
+                    count = page_len,
+                    "Fetched list members page"
+                );
+                list.members
+                    .extend(page.into_iter().map(|item| item.item_id));
+                offset += page_len;
+                Self::throttle(total_requests).await;
+
+                if page_len < PAGE_SIZE {
+                    break;
+                }
+            }
+        }
+
+        Ok(lists)
     }
 }
@@ -0,0 +1,297 @@
+// Keeps a trimmed down copy of every successful sync on disk so we can look at how things change over time.
+// Each snapshot is its own file in the history directory, named after the time it was taken.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::handlers::config::{write_atomic, SyncData};
+use crate::handlers::overrides::overrides;
+use crate::handlers::time::now_ms;
+use crate::structs::{
+    AutoRenew, BillingCycle, FanslyFollowersResponse, Money, Subscription, SubscriptionStatus,
+    SyncDataResponse,
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionSnapshot {
+    pub id: String,
+    pub subscriber_id: String,
+    #[serde(default)]
+    pub subscription_tier_id: String,
+    #[serde(default)]
+    pub subscription_tier_name: String,
+    #[serde(default)]
+    pub status: SubscriptionStatus,
+    #[serde(default)]
+    pub price: Money,
+    #[serde(default)]
+    pub auto_renew: AutoRenew,
+    #[serde(default)]
+    pub billing_cycle: BillingCycle,
+    #[serde(default)]
+    pub renew_date: i64,
+    #[serde(default)]
+    pub ends_at: i64,
+    #[serde(default)]
+    pub created_at: i64,
+}
+
+impl SubscriptionSnapshot {
+    pub fn is_active(&self) -> bool {
+        self.status == SubscriptionStatus::Active
+    }
+}
+
+impl From<&Subscription> for SubscriptionSnapshot {
+    fn from(subscription: &Subscription) -> Self {
+        Self {
+            id: subscription.id.clone(),
+            subscriber_id: subscription.subscriber_id.clone(),
+            subscription_tier_id: subscription.subscription_tier_id.clone(),
+            subscription_tier_name: subscription.subscription_tier_name.clone(),
+            status: subscription.status,
+            price: subscription.price,
+            auto_renew: subscription.auto_renew,
+            billing_cycle: subscription.billing_cycle,
+            renew_date: subscription.renew_date,
+            ends_at: subscription.ends_at,
+            created_at: subscription.created_at,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSnapshot {
+    // When the sync finished (UNIX timestamp in milliseconds)
+    pub taken_at: i64,
+    // Follower account ids
+    pub followers: Vec<String>,
+    pub subscriptions: Vec<SubscriptionSnapshot>,
+    // Totals from the profile, these can differ from what the sync fetched
+    #[serde(default)]
+    pub follow_count: i64,
+    #[serde(default)]
+    pub subscriber_count: i64,
+    // The subscription statuses the sync asked Fansly for. Empty when we don't know, for snapshots
+    // saved before this was kept and for the sync saved in the config.
+    #[serde(default)]
+    pub statuses: Vec<SubscriptionStatus>,
+}
+
+impl SyncSnapshot {
+    fn new(
+        followers: &[FanslyFollowersResponse],
+        subscribers: &[Subscription],
+        taken_at: i64,
+    ) -> Self {
+        Self {
+            taken_at,
+            followers: followers
+                .iter()
+                .map(|follower| follower.follower_id.clone())
+                .collect(),
+            subscriptions: subscribers.iter().map(SubscriptionSnapshot::from).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_sync(
+        data: &SyncDataResponse,
+        statuses: &[SubscriptionStatus],
+        taken_at: i64,
+    ) -> Self {
+        Self {
+            follow_count: data.follow_count,
+            subscriber_count: data.subscriber_count,
+            statuses: statuses.to_vec(),
+            ..Self::new(&data.followers, &data.subscribers, taken_at)
+        }
+    }
+
+    // Whether both syncs are known to have asked for the same subscriptions, so one missing from
+    // the other really is gone. Unknown statuses could have been anything, so they never match.
+    pub fn same_statuses_as(&self, other: &SyncSnapshot) -> bool {
+        !self.statuses.is_empty() && self.statuses == other.statuses
+    }
+
+    // The sync data saved in the config doesn't have the profile totals, so those stay at 0
+    pub fn from_saved(data: &SyncData, taken_at: i64) -> Self {
+        Self::new(&data.followers, &data.subscribers, taken_at)
+    }
+
+    // One subscription per subscriber: their active one if there is one, otherwise the most recent
+    pub fn by_subscriber(&self) -> HashMap<&str, &SubscriptionSnapshot> {
+        let mut subscribers: HashMap<&str, &SubscriptionSnapshot> = HashMap::new();
+
+        for subscription in &self.subscriptions {
+            let key = |s: &SubscriptionSnapshot| (s.is_active(), s.created_at);
+            subscribers
+                .entry(subscription.subscriber_id.as_str())
+                .and_modify(|current| {
+                    if key(subscription) > key(current) {
+                        *current = subscription;
+                    }
+                })
+                .or_insert(subscription);
+        }
+
+        subscribers
+    }
+}
+
+pub fn get_history_dir() -> io::Result<PathBuf> {
+    let history_dir = overrides().data_dir()?.join("history");
+    fs::create_dir_all(&history_dir)?;
+    Ok(history_dir)
+}
+
+// Snapshot files sorted oldest first. Files that don't look like snapshots are ignored.
+fn snapshot_files(dir: &Path) -> io::Result<Vec<(i64, PathBuf)>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+
+        let taken_at = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<i64>().ok());
+
+        if let Some(taken_at) = taken_at {
+            files.push((taken_at, path));
+        }
+    }
+
+    files.sort_by_key(|(taken_at, _)| *taken_at);
+    Ok(files)
+}
+
+fn read_snapshot(path: &Path) -> Result<SyncSnapshot, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
+
+// Save a snapshot and drop the oldest ones so at most `limit` are kept. A limit of 0 keeps everything.
+pub fn record(dir: &Path, snapshot: &SyncSnapshot, limit: usize) -> io::Result<()> {
+    let path = dir.join(format!("{}.json", snapshot.taken_at));
+    write_atomic(&path, serde_json::to_string(snapshot)?.as_bytes())?;
+
+    log::info!(
+        "[history::record] Saved sync snapshot with {} followers and {} subscriptions",
+        snapshot.followers.len(),
+        snapshot.subscriptions.len()
+    );
+
+    if limit == 0 {
+        return Ok(());
+    }
+
+    let files = snapshot_files(dir)?;
+    if files.len() > limit {
+        let excess = files.len() - limit;
+        for (_, path) in files.into_iter().take(excess) {
+            fs::remove_file(&path)?;
+        }
+        log::info!("[history::record] Pruned {} old sync snapshots", excess);
+    }
+
+    Ok(())
+}
+
+// All stored snapshots, oldest first. Unreadable snapshots are skipped rather than failing the whole load.
+pub fn load_all(dir: &Path) -> io::Result<Vec<SyncSnapshot>> {
+    let mut snapshots = Vec::new();
+
+    for (_, path) in snapshot_files(dir)? {
+        match read_snapshot(&path) {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(e) => log::warn!(
+                "[history::load_all] Skipping unreadable snapshot {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    Ok(snapshots)
+}
+
+// The most recent snapshot in the history directory, if there is a readable one
+pub fn load_latest(dir: &Path) -> io::Result<Option<SyncSnapshot>> {
+    for (_, path) in snapshot_files(dir)?.into_iter().rev() {
+        match read_snapshot(&path) {
+            Ok(snapshot) => return Ok(Some(snapshot)),
+            Err(e) => log::warn!(
+                "[history::load_latest] Skipping unreadable snapshot {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    Ok(None)
+}
+
+// Snapshot a finished sync that asked for `statuses`, stamped with the current time
+pub fn snapshot_sync(data: &SyncDataResponse, statuses: &[SubscriptionStatus]) -> SyncSnapshot {
+    SyncSnapshot::from_sync(data, statuses, now_ms())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(taken_at: i64) -> SyncSnapshot {
+        SyncSnapshot {
+            taken_at,
+            followers: vec![taken_at.to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn latest_skips_snapshots_it_cant_read() {
+        let dir = std::env::temp_dir().join(format!("fanslysync-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        record(&dir, &snapshot(1), 0).unwrap();
+        record(&dir, &snapshot(2), 0).unwrap();
+        fs::write(dir.join("3.json"), "{ not json").unwrap();
+        // Reading a directory fails with an IO error rather than a parse error
+        fs::create_dir(dir.join("4.json")).unwrap();
+
+        let latest = load_latest(&dir).unwrap().unwrap();
+        assert_eq!(latest.taken_at, 2);
+        assert_eq!(load_all(&dir).unwrap().len(), 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn recording_prunes_the_oldest() {
+        let dir = std::env::temp_dir().join(format!("fanslysync-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for taken_at in 1..=4 {
+            record(&dir, &snapshot(taken_at), 2).unwrap();
+        }
+
+        let kept: Vec<i64> = load_all(&dir)
+            .unwrap()
+            .iter()
+            .map(|snapshot| snapshot.taken_at)
+            .collect();
+        assert_eq!(kept, vec![3, 4]);
+        // Nothing left over from the atomic writes
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,44 @@
+// Structured logging. With `log_json` on, every tracing event is also written as a JSON line to
+// <data dir>/runtime.jsonl, together with the spans it happened in: the sync run id and phase, and
+// the endpoint, offset, status code and duration of the request. The plain text log is unchanged.
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::sync::Mutex;
+
+use crate::handlers::config;
+use crate::handlers::diagnostics::LOG_FILE_STEM;
+use crate::handlers::overrides::overrides;
+
+// Same limit as the text log, one older file is kept
+const MAX_FILE_SIZE: u64 = 1024 * 1024 * 5;
+
+// Start writing JSON log lines if the config asks for it. Returns whether it did.
+pub fn init_json_log() -> io::Result<bool> {
+    if !config::current()?.log_json {
+        return Ok(false);
+    }
+
+    let dir = overrides().data_dir()?;
+    let path = dir.join(format!("{}.jsonl", LOG_FILE_STEM));
+
+    if fs::metadata(&path).is_ok_and(|metadata| metadata.len() > MAX_FILE_SIZE) {
+        fs::rename(&path, dir.join(format!("{}.1.jsonl", LOG_FILE_STEM)))?;
+    }
+
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_writer(Mutex::new(file))
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_max_level(tracing::Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber).map_err(io::Error::other)?;
+
+    log::info!(
+        "[logging::init_json_log] Writing JSON logs to {}",
+        path.display()
+    );
+    Ok(true)
+}
@@ -1,2 +1,16 @@
+pub mod analytics;
+pub mod chat;
 pub mod config;
+pub mod delta;
+pub mod diagnostics;
 pub mod fansly;
+pub mod history;
+pub mod logging;
+pub mod notifications;
+pub mod overrides;
+pub mod payload;
+pub mod redact;
+pub mod scheduler;
+pub mod signing;
+pub mod time;
+pub mod webhooks;
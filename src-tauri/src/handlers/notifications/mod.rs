@@ -0,0 +1,226 @@
+// Turns sync deltas into desktop notifications. Sending them is up to the caller, this only decides what to say.
+use std::collections::BTreeMap;
+
+use crate::handlers::config::Config;
+use crate::handlers::delta::{Milestone, SubscriberEvent, SyncDelta};
+use crate::structs::{display_name, AccountSummary};
+
+// Past this many events of one kind we send a single summary instead of one notification each
+const MAX_INDIVIDUAL_NOTICES: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotificationSettings {
+    pub new_subscribers: bool,
+    pub tier_changes: bool,
+    pub cancellations: bool,
+    pub milestones: bool,
+    pub sync_failures: bool,
+    // Batch everything from one sync into a single notification
+    pub digest: bool,
+}
+
+impl NotificationSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            new_subscribers: config.notify_new_subscribers,
+            tier_changes: config.notify_tier_changes,
+            cancellations: config.notify_cancellations,
+            milestones: config.notify_milestones,
+            sync_failures: config.notify_sync_failures,
+            digest: config.notify_digest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notice {
+    pub title: String,
+    pub body: String,
+}
+
+impl Notice {
+    fn new(title: &str, body: String) -> Self {
+        Self {
+            title: format!("FanslySync: {}", title),
+            body,
+        }
+    }
+}
+
+fn plural(count: usize, singular: &str, plural: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, singular)
+    } else {
+        format!("{} {}", count, plural)
+    }
+}
+
+fn milestone_line(what: &str, milestone: &Milestone) -> String {
+    format!(
+        "You passed {} {} ({} now)",
+        milestone.reached, what, milestone.current
+    )
+}
+
+// One line per enabled event kind for the digest notification
+fn summary_lines(delta: &SyncDelta, settings: &NotificationSettings) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    if settings.new_subscribers && !delta.new_subscribers.is_empty() {
+        lines.push(plural(
+            delta.new_subscribers.len(),
+            "new subscriber",
+            "new subscribers",
+        ));
+    }
+    if settings.tier_changes && !delta.tier_changes.is_empty() {
+        let upgrades = delta.tier_changes.iter().filter(|c| c.upgrade).count();
+        lines.push(format!(
+            "{} ({} up, {} down)",
+            plural(delta.tier_changes.len(), "tier change", "tier changes"),
+            upgrades,
+            delta.tier_changes.len() - upgrades
+        ));
+    }
+    if settings.cancellations && !delta.cancellations.is_empty() {
+        lines.push(plural(
+            delta.cancellations.len(),
+            "cancellation",
+            "cancellations",
+        ));
+    }
+    if settings.milestones {
+        if let Some(milestone) = &delta.follower_milestone {
+            lines.push(milestone_line("followers", milestone));
+        }
+        if let Some(milestone) = &delta.subscriber_milestone {
+            lines.push(milestone_line("subscribers", milestone));
+        }
+    }
+
+    lines
+}
+
+fn subscriber_notices(
+    events: &[SubscriberEvent],
+    title: &str,
+    verb: &str,
+    summary: (&str, &str),
+    accounts: Option<&BTreeMap<String, AccountSummary>>,
+) -> Vec<Notice> {
+    if events.len() > MAX_INDIVIDUAL_NOTICES {
+        let (singular, plural_form) = summary;
+        return vec![Notice::new(
+            title,
+            plural(events.len(), singular, plural_form),
+        )];
+    }
+
+    events
+        .iter()
+        .map(|event| {
+            Notice::new(
+                title,
+                format!(
+                    "{} {} {}",
+                    display_name(&event.subscriber_id, accounts),
+                    verb,
+                    event.subscription_tier_name
+                ),
+            )
+        })
+        .collect()
+}
+
+// Everything worth telling the user about after a sync
+pub fn sync_notices(
+    delta: &SyncDelta,
+    settings: &NotificationSettings,
+    accounts: Option<&BTreeMap<String, AccountSummary>>,
+) -> Vec<Notice> {
+    if settings.digest {
+        let lines = summary_lines(delta, settings);
+        if lines.is_empty() {
+            return Vec::new();
+        }
+        return vec![Notice::new("Sync Summary", lines.join("\n"))];
+    }
+
+    let mut notices = Vec::new();
+
+    if settings.new_subscribers {
+        notices.extend(subscriber_notices(
+            &delta.new_subscribers,
+            "New Subscriber",
+            "subscribed to",
+            ("new subscriber", "new subscribers"),
+            accounts,
+        ));
+    }
+
+    if settings.tier_changes {
+        if delta.tier_changes.len() > MAX_INDIVIDUAL_NOTICES {
+            notices.push(Notice::new(
+                "Tier Changes",
+                plural(delta.tier_changes.len(), "tier change", "tier changes"),
+            ));
+        } else {
+            for change in &delta.tier_changes {
+                let (title, verb) = if change.upgrade {
+                    ("Tier Upgrade", "upgraded")
+                } else {
+                    ("Tier Downgrade", "downgraded")
+                };
+                notices.push(Notice::new(
+                    title,
+                    format!(
+                        "{} {} from {} to {}",
+                        display_name(&change.subscriber_id, accounts),
+                        verb,
+                        change.from_tier_name,
+                        change.to_tier_name
+                    ),
+                ));
+            }
+        }
+    }
+
+    if settings.cancellations {
+        notices.extend(subscriber_notices(
+            &delta.cancellations,
+            "Subscription Cancelled",
+            "cancelled",
+            ("cancellation", "cancellations"),
+            accounts,
+        ));
+    }
+
+    if settings.milestones {
+        if let Some(milestone) = &delta.follower_milestone {
+            notices.push(Notice::new(
+                "Follower Milestone",
+                milestone_line("followers", milestone),
+            ));
+        }
+        if let Some(milestone) = &delta.subscriber_milestone {
+            notices.push(Notice::new(
+                "Subscriber Milestone",
+                milestone_line("subscribers", milestone),
+            ));
+        }
+    }
+
+    notices
+}
+
+pub fn sync_failed_notice(auto: bool, error: &str) -> Notice {
+    let title = if auto {
+        "Auto Sync Failed!"
+    } else {
+        "Sync Failed!"
+    };
+    Notice::new(
+        title,
+        format!("An error occurred while syncing data: {}", error),
+    )
+}
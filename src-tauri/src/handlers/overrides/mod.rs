@@ -0,0 +1,338 @@
+// Settings that come from the environment or the command line instead of the config file, for isolated
+// instances and containerized syncs. Overrides win over the config file but are never written back to it.
+//
+//   --config-dir <dir>    FANSLYSYNC_CONFIG_DIR    Keep config, logs and history in <dir>
+//   --portable            FANSLYSYNC_PORTABLE=1    Keep them in a "data" folder next to the executable
+//   --sync-interval <h>   FANSLYSYNC_SYNC_INTERVAL
+//   --auto-sync           FANSLYSYNC_AUTO_SYNC=1
+//   --log-json            FANSLYSYNC_LOG_JSON=1    Write structured JSON logs to runtime.jsonl
+//                         FANSLYSYNC_TOKEN         The Fansly token
+//                         FANSLYSYNC_SYNC_TOKEN
+//
+// Tokens are only read from the environment, command lines show up in process lists.
+// A file named "portable" next to the executable also turns on portable mode, so launches
+// without arguments (autostart, double-clicking) stay portable.
+use lazy_static::lazy_static;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::handlers::config::{Config, MAX_SYNC_INTERVAL_HOURS};
+
+const PORTABLE_MARKER: &str = "portable";
+const PORTABLE_DIR: &str = "data";
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Overrides {
+    pub config_dir: Option<PathBuf>,
+    pub portable: bool,
+    pub fansly_token: Option<String>,
+    pub sync_token: Option<String>,
+    pub sync_interval: Option<u64>,
+    pub auto_sync_enabled: Option<bool>,
+    pub log_json: Option<bool>,
+}
+
+lazy_static! {
+    static ref OVERRIDES: Overrides = Overrides::from_env_and_args(
+        &std::env::vars().collect::<Vec<_>>(),
+        &std::env::args().skip(1).collect::<Vec<_>>(),
+    );
+}
+
+pub fn overrides() -> &'static Overrides {
+    &OVERRIDES
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_interval(source: &str, value: &str) -> Option<u64> {
+    let interval = value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|hours| (1..=MAX_SYNC_INTERVAL_HOURS).contains(hours));
+    if interval.is_none() {
+        log::warn!(
+            "[overrides] Ignoring {}, expected a number of hours between 1 and {} but got {:?}",
+            source,
+            MAX_SYNC_INTERVAL_HOURS,
+            value
+        );
+    }
+    interval
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.trim().is_empty()).then(|| value.to_string())
+}
+
+fn exe_dir() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(PathBuf::from))
+}
+
+impl Overrides {
+    // Command line arguments take precedence over environment variables
+    fn from_env_and_args(env: &[(String, String)], args: &[String]) -> Self {
+        let mut overrides = Overrides::default();
+
+        for (name, value) in env {
+            match name.as_str() {
+                "FANSLYSYNC_CONFIG_DIR" => {
+                    overrides.config_dir = non_empty(value).map(PathBuf::from)
+                }
+                "FANSLYSYNC_PORTABLE" => overrides.portable = parse_bool(value).unwrap_or(false),
+                "FANSLYSYNC_TOKEN" => overrides.fansly_token = non_empty(value),
+                "FANSLYSYNC_SYNC_TOKEN" => overrides.sync_token = non_empty(value),
+                "FANSLYSYNC_SYNC_INTERVAL" => overrides.sync_interval = parse_interval(name, value),
+                "FANSLYSYNC_AUTO_SYNC" => overrides.auto_sync_enabled = parse_bool(value),
+                "FANSLYSYNC_LOG_JSON" => overrides.log_json = parse_bool(value),
+                _ => {}
+            }
+        }
+
+        let mut args = args.iter().map(String::as_str);
+        while let Some(arg) = args.next() {
+            // Both "--flag value" and "--flag=value"
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value)),
+                None => (arg, None),
+            };
+
+            match flag {
+                "--portable" => overrides.portable = true,
+                "--auto-sync" => overrides.auto_sync_enabled = Some(true),
+                "--no-auto-sync" => overrides.auto_sync_enabled = Some(false),
+                "--log-json" => overrides.log_json = Some(true),
+                "--config-dir" => {
+                    if let Some(dir) = inline_value.or_else(|| args.next()).and_then(non_empty) {
+                        overrides.config_dir = Some(PathBuf::from(dir));
+                    }
+                }
+                "--sync-interval" => {
+                    if let Some(value) = inline_value.or_else(|| args.next()) {
+                        overrides.sync_interval = parse_interval(flag, value);
+                    }
+                }
+                // Anything else is for Tauri or the OS (e.g. macOS process serial numbers)
+                _ => {}
+            }
+        }
+
+        overrides
+    }
+
+    fn is_portable(&self) -> bool {
+        self.is_portable_in(exe_dir().as_deref())
+    }
+
+    // Portable when asked for, or when the marker file sits in the executable's directory
+    fn is_portable_in(&self, exe_dir: Option<&Path>) -> bool {
+        self.portable || exe_dir.is_some_and(|dir| dir.join(PORTABLE_MARKER).exists())
+    }
+
+    // Where config, logs and history live: an explicit directory, then portable mode, then the user's config directory
+    pub fn data_dir(&self) -> io::Result<PathBuf> {
+        let dir = if let Some(dir) = &self.config_dir {
+            dir.clone()
+        } else if self.is_portable() {
+            exe_dir()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "Could not determine the executable's directory for portable mode",
+                    )
+                })?
+                .join(PORTABLE_DIR)
+        } else {
+            let mut dir = dirs::config_dir().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "Could not determine user's config directory",
+                )
+            })?;
+            dir.push("FanslySync");
+            dir
+        };
+
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(token) = &self.fansly_token {
+            config.fansly_token = token.clone();
+        }
+        if let Some(token) = &self.sync_token {
+            config.sync_token = token.clone();
+        }
+        if let Some(interval) = self.sync_interval {
+            config.sync_interval = interval;
+        }
+        if let Some(enabled) = self.auto_sync_enabled {
+            config.auto_sync_enabled = enabled;
+        }
+        if let Some(enabled) = self.log_json {
+            config.log_json = enabled;
+        }
+    }
+
+    // Put the stored values back for every overridden field, so a config the frontend
+    // got from us (overrides included) can be saved without persisting the overrides
+    pub fn keep_stored(&self, config: &mut Config, stored: &Config) {
+        if self.fansly_token.is_some() {
+            config.fansly_token = stored.fansly_token.clone();
+        }
+        if self.sync_token.is_some() {
+            config.sync_token = stored.sync_token.clone();
+        }
+        if self.sync_interval.is_some() {
+            config.sync_interval = stored.sync_interval;
+        }
+        if self.auto_sync_enabled.is_some() {
+            config.auto_sync_enabled = stored.auto_sync_enabled;
+        }
+        if self.log_json.is_some() {
+            config.log_json = stored.log_json;
+        }
+    }
+
+    // Names of the active overrides, never their values
+    pub fn active(&self) -> Vec<&'static str> {
+        [
+            ("config_dir", self.config_dir.is_some()),
+            ("portable", self.is_portable()),
+            ("fansly_token", self.fansly_token.is_some()),
+            ("sync_token", self.sync_token.is_some()),
+            ("sync_interval", self.sync_interval.is_some()),
+            ("auto_sync_enabled", self.auto_sync_enabled.is_some()),
+            ("log_json", self.log_json.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, active)| active.then_some(name))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn reads_the_environment() {
+        let overrides = Overrides::from_env_and_args(
+            &env(&[
+                ("FANSLYSYNC_CONFIG_DIR", "/tmp/fanslysync"),
+                ("FANSLYSYNC_TOKEN", "token"),
+                ("FANSLYSYNC_SYNC_TOKEN", " "),
+                ("FANSLYSYNC_SYNC_INTERVAL", "6"),
+                ("FANSLYSYNC_AUTO_SYNC", "yes"),
+                ("FANSLYSYNC_LOG_JSON", "maybe"),
+                ("PATH", "/usr/bin"),
+            ]),
+            &[],
+        );
+
+        assert_eq!(overrides.config_dir, Some(PathBuf::from("/tmp/fanslysync")));
+        assert_eq!(overrides.fansly_token.as_deref(), Some("token"));
+        assert_eq!(overrides.sync_token, None);
+        assert_eq!(overrides.sync_interval, Some(6));
+        assert_eq!(overrides.auto_sync_enabled, Some(true));
+        assert_eq!(overrides.log_json, None);
+        assert!(!overrides.portable);
+    }
+
+    #[test]
+    fn arguments_win_over_the_environment() {
+        let overrides = Overrides::from_env_and_args(
+            &env(&[
+                ("FANSLYSYNC_SYNC_INTERVAL", "6"),
+                ("FANSLYSYNC_AUTO_SYNC", "1"),
+            ]),
+            &args(&[
+                "--sync-interval=12",
+                "--no-auto-sync",
+                "--config-dir",
+                "/tmp/other",
+                "-psn_0_12345",
+                "--portable",
+                "--log-json",
+            ]),
+        );
+
+        assert_eq!(overrides.sync_interval, Some(12));
+        assert_eq!(overrides.auto_sync_enabled, Some(false));
+        assert_eq!(overrides.config_dir, Some(PathBuf::from("/tmp/other")));
+        assert!(overrides.portable);
+        assert_eq!(overrides.log_json, Some(true));
+    }
+
+    #[test]
+    fn out_of_range_intervals_are_ignored() {
+        for interval in ["0", "-1", "hourly", "100000"] {
+            let overrides =
+                Overrides::from_env_and_args(&[], &args(&["--sync-interval", interval]));
+            assert_eq!(overrides.sync_interval, None, "{}", interval);
+        }
+    }
+
+    #[test]
+    fn marker_file_turns_on_portable_mode() {
+        let dir = std::env::temp_dir().join(format!("fanslysync-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let overrides = Overrides::default();
+
+        assert!(!overrides.is_portable_in(Some(&dir)));
+        assert!(!overrides.is_portable_in(None));
+
+        fs::write(dir.join(PORTABLE_MARKER), "").unwrap();
+        assert!(overrides.is_portable_in(Some(&dir)));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn overridden_fields_are_saved_as_stored() {
+        let overrides = Overrides {
+            sync_token: Some("from env".to_string()),
+            sync_interval: Some(12),
+            ..Default::default()
+        };
+        let stored = Config {
+            sync_token: "stored".to_string(),
+            sync_interval: 2,
+            ..Default::default()
+        };
+
+        let mut config = stored.clone();
+        overrides.apply(&mut config);
+        assert_eq!(config.sync_token, "from env");
+        assert_eq!(config.sync_interval, 12);
+
+        // The frontend sends the effective config back with a change of its own
+        config.sync_earnings = true;
+        overrides.keep_stored(&mut config, &stored);
+        assert_eq!(config.sync_token, "stored");
+        assert_eq!(config.sync_interval, 2);
+        assert!(config.sync_earnings);
+    }
+}
@@ -0,0 +1,277 @@
+// Trims sync data down to what the receiving side actually needs before we upload it.
+// Filtering happens on the serialized JSON so the field names match what gets sent
+// over the wire (camelCase, as documented in DEVELOPERS.md).
+use serde_json::Value;
+
+use crate::handlers::config::Config;
+use crate::structs::{SyncDataResponse, UploadProfile};
+
+// The subscription fields sent with the standard profile: everything we know about except payment and
+// promo details. Fields Fansly adds later are left out until someone decides they are fine to share.
+const STANDARD_FIELDS: &[&str] = &[
+    "id",
+    "historyId",
+    "subscriberId",
+    "subscriptionTierId",
+    "subscriptionTierName",
+    "subscriptionTierColor",
+    "planId",
+    "status",
+    "price",
+    "renewPrice",
+    "autoRenew",
+    "billingCycle",
+    "duration",
+    "renewDate",
+    "version",
+    "createdAt",
+    "updatedAt",
+    "endsAt",
+];
+
+// The only subscription fields sent with the minimal profile
+const MINIMAL_FIELDS: &[&str] = &[
+    "id",
+    "subscriberId",
+    "subscriptionTierId",
+    "status",
+    "renewDate",
+    "endsAt",
+];
+
+// Account details sent with the standard profile, enough to show who someone is
+const STANDARD_ACCOUNT_FIELDS: &[&str] = &["id", "username"];
+
+// List details sent with the standard profile. Which fans the creator put on which list stays private.
+const STANDARD_LIST_FIELDS: &[&str] = &["id", "name", "itemCount", "createdAt"];
+
+// Top level fields the minimal profile leaves out entirely
+const MINIMAL_DROPPED: &[&str] = &["earnings", "accounts", "lists", "following"];
+
+// Keep only `keep` in every entry of a list, or of a map keyed by id
+fn retain_fields(value: Option<&mut Value>, keep: &[&str]) {
+    let entries: Vec<&mut Value> = match value {
+        Some(Value::Array(entries)) => entries.iter_mut().collect(),
+        Some(Value::Object(entries)) => entries.values_mut().collect(),
+        _ => return,
+    };
+
+    for entry in entries {
+        if let Some(fields) = entry.as_object_mut() {
+            fields.retain(|field, _| keep.contains(&field.as_str()));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PayloadFilter {
+    pub profile: UploadProfile,
+    // Subscription fields (camelCase) to keep on top of the profile. Empty keeps everything the profile allows.
+    pub allowlist: Vec<String>,
+}
+
+impl PayloadFilter {
+    pub fn new(profile: UploadProfile, allowlist: Vec<String>) -> Self {
+        Self { profile, allowlist }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.upload_profile, config.upload_field_allowlist.clone())
+    }
+
+    // Whether a subscription field survives the profile and the allowlist
+    fn keeps_field(&self, field: &str) -> bool {
+        let allowed_by_profile = match self.profile {
+            UploadProfile::Full => true,
+            UploadProfile::Standard => STANDARD_FIELDS.contains(&field),
+            UploadProfile::Minimal => MINIMAL_FIELDS.contains(&field),
+        };
+
+        allowed_by_profile
+            && (self.allowlist.is_empty() || self.allowlist.iter().any(|f| f == field))
+    }
+
+    // Serialize the sync data and strip every subscription field we don't want to share
+    pub fn apply(&self, data: &SyncDataResponse) -> Result<Value, serde_json::Error> {
+        let mut value = serde_json::to_value(data)?;
+
+        if let Some(subscribers) = value.get_mut("subscribers").and_then(Value::as_array_mut) {
+            for subscriber in subscribers.iter_mut() {
+                if let Some(fields) = subscriber.as_object_mut() {
+                    fields.retain(|field, _| self.keeps_field(field));
+                }
+            }
+        }
+
+        // Earnings are financial data, only the full profile shares individual transactions.
+        // Account details and list members are about fans rather than subscriptions, so they are cut
+        // down the same way.
+        match self.profile {
+            UploadProfile::Full => {}
+            UploadProfile::Standard => {
+                if let Some(earnings) = value.get_mut("earnings").and_then(Value::as_object_mut) {
+                    earnings.remove("transactions");
+                }
+                retain_fields(value.get_mut("accounts"), STANDARD_ACCOUNT_FIELDS);
+                retain_fields(value.get_mut("lists"), STANDARD_LIST_FIELDS);
+            }
+            UploadProfile::Minimal => {
+                if let Some(fields) = value.as_object_mut() {
+                    for field in MINIMAL_DROPPED {
+                        fields.remove(*field);
+                    }
+                }
+            }
+        }
+
+        log::debug!(
+            "[payload::apply] Applied upload profile {:?} with {} allowlisted fields",
+            self.profile,
+            self.allowlist.len()
+        );
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{AccountSummary, FanslyFollowingResponse, FanslyList, Subscription};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn data_with_subscriber(subscriber: Value) -> SyncDataResponse {
+        SyncDataResponse {
+            subscribers: vec![serde_json::from_value::<Subscription>(subscriber).unwrap()],
+            ..Default::default()
+        }
+    }
+
+    fn subscriber_fields(filter: &PayloadFilter, data: &SyncDataResponse) -> Vec<String> {
+        let value = filter.apply(data).unwrap();
+        let mut fields: Vec<String> = value["subscribers"][0]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        fields.sort();
+        fields
+    }
+
+    #[test]
+    fn standard_drops_fields_fansly_adds_later() {
+        let data = data_with_subscriber(json!({
+            "id": "1",
+            "subscriberId": "2",
+            "paymentMethodId": "3",
+            "subscriberEmail": "fan@example.com",
+        }));
+
+        let full = subscriber_fields(&PayloadFilter::new(UploadProfile::Full, vec![]), &data);
+        assert!(full.contains(&"subscriberEmail".to_string()));
+
+        let standard =
+            subscriber_fields(&PayloadFilter::new(UploadProfile::Standard, vec![]), &data);
+        assert!(!standard.contains(&"subscriberEmail".to_string()));
+        assert!(!standard.contains(&"paymentMethodId".to_string()));
+        assert!(standard
+            .iter()
+            .all(|field| STANDARD_FIELDS.contains(&field.as_str())));
+        assert!(standard.contains(&"subscriberId".to_string()));
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn every_profile_cuts_down_accounts_lists_and_following() {
+        let data = SyncDataResponse {
+            following: Some(vec![FanslyFollowingResponse {
+                account_id: "3".to_string(),
+            }]),
+            lists: Some(vec![FanslyList {
+                id: "4".to_string(),
+                name: "Regulars".to_string(),
+                members: vec!["2".to_string()],
+                ..Default::default()
+            }]),
+            accounts: Some(BTreeMap::from([(
+                "2".to_string(),
+                AccountSummary {
+                    id: "2".to_string(),
+                    username: "fan".to_string(),
+                    display_name: Some("Fan".to_string()),
+                    avatar_id: Some("5".to_string()),
+                    ..Default::default()
+                },
+            )])),
+            ..data_with_subscriber(json!({ "id": "1", "subscriberId": "2" }))
+        };
+        let apply = |profile| PayloadFilter::new(profile, vec![]).apply(&data).unwrap();
+
+        let full = apply(UploadProfile::Full);
+        assert_eq!(
+            keys(&full),
+            vec![
+                "accounts",
+                "follow_count",
+                "followers",
+                "following",
+                "lists",
+                "subscriber_count",
+                "subscribers",
+                "sync_data_url"
+            ]
+        );
+        assert_eq!(
+            keys(&full["accounts"]["2"]),
+            vec!["avatarId", "createdAt", "displayName", "id", "username"]
+        );
+        assert_eq!(
+            keys(&full["lists"][0]),
+            vec!["createdAt", "id", "itemCount", "members", "name"]
+        );
+
+        let standard = apply(UploadProfile::Standard);
+        assert_eq!(keys(&standard), keys(&full));
+        assert_eq!(keys(&standard["accounts"]["2"]), vec!["id", "username"]);
+        assert_eq!(
+            keys(&standard["lists"][0]),
+            vec!["createdAt", "id", "itemCount", "name"]
+        );
+        assert_eq!(standard["following"], full["following"]);
+
+        let minimal = apply(UploadProfile::Minimal);
+        assert_eq!(
+            keys(&minimal),
+            vec![
+                "follow_count",
+                "followers",
+                "subscriber_count",
+                "subscribers",
+                "sync_data_url"
+            ]
+        );
+    }
+
+    #[test]
+    fn allowlist_narrows_the_profile() {
+        let data = data_with_subscriber(json!({ "id": "1", "subscriberId": "2", "promoId": "3" }));
+        let filter = PayloadFilter::new(
+            UploadProfile::Standard,
+            vec!["subscriberId".to_string(), "promoId".to_string()],
+        );
+
+        assert_eq!(subscriber_fields(&filter, &data), vec!["subscriberId"]);
+    }
+}
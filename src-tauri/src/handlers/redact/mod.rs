@@ -0,0 +1,139 @@
+// Helpers for keeping tokens and personal details out of logs.
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+
+// Keys whose values never make it into logs, compared case-insensitively.
+// Anything ending in "token" is treated as sensitive as well.
+const SENSITIVE_KEYS: &[&str] = &[
+    "authorization",
+    "email",
+    "paymentmethodid",
+    "password",
+    "secret",
+];
+
+const REDACTED: &str = "[redacted]";
+
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SENSITIVE_KEYS.iter().any(|sensitive| key == *sensitive) || key.ends_with("token")
+}
+
+// Replace the values of sensitive keys anywhere in the JSON tree
+pub fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, inner) in map.iter_mut() {
+                if is_sensitive(key) {
+                    *inner = Value::String(REDACTED.to_string());
+                } else {
+                    redact_value(inner);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+// A short, redacted excerpt of a response body that is safe to log
+pub fn body_snippet(body: &str, max_len: usize) -> String {
+    let snippet = match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        // Not JSON, so we can't tell what is sensitive. Only log its size.
+        Err(_) => return format!("<{} bytes of non-JSON body>", body.len()),
+    };
+
+    if snippet.chars().count() > max_len {
+        let truncated: String = snippet.chars().take(max_len).collect();
+        format!("{}... ({} bytes total)", truncated, body.len())
+    } else {
+        snippet
+    }
+}
+
+lazy_static! {
+    // `key: "value"`, `"key":"value"` and `key=value` where the key looks sensitive,
+    // which covers Debug output of our own structs as well as headers and JSON bodies.
+    // Authorization headers keep their credentials after the scheme, so that goes too.
+    static ref SENSITIVE_PAIR: Regex = Regex::new(
+        r#"(?i)("?[\w-]*(?:token|secret|authorization|password)"?\s*[:=]\s*)("[^"]*"|(?:(?:bearer|basic)\s+)?[^\s,;}\]]+)"#
+    )
+    .unwrap();
+    // Discord and Slack webhook URLs carry their credentials in the path
+    static ref CHAT_WEBHOOK_URL: Regex = Regex::new(
+        r"(?i)https://(?:(?:canary\.|ptb\.)?discord(?:app)?\.com/api/webhooks|hooks\.slack\.com/services)/[^\s\x22']+"
+    )
+    .unwrap();
+    static ref EMAIL: Regex = Regex::new(r"[\w.+-]+@[\w-]+(?:\.[\w-]+)+").unwrap();
+}
+
+// Redact free-form text such as log files. `secrets` are values known to be sensitive
+// (e.g. the tokens in the config) and are removed wherever they appear.
+pub fn redact_text(text: &str, secrets: &[String]) -> String {
+    let mut text = text.to_string();
+
+    // Very short values would match all over the place, and can't be much of a secret anyway
+    for secret in secrets.iter().filter(|secret| secret.len() >= 6) {
+        text = text.replace(secret.as_str(), REDACTED);
+    }
+
+    let text = SENSITIVE_PAIR.replace_all(&text, format!("${{1}}\"{}\"", REDACTED));
+    let text = CHAT_WEBHOOK_URL.replace_all(&text, REDACTED);
+    EMAIL.replace_all(&text, REDACTED).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn log_files_are_redacted() {
+        let redacted = redact_text(
+            include_str!("fixtures/runtime.log"),
+            &["a-known-secret-value".to_string(), "short".to_string()],
+        );
+        assert_eq!(redacted, include_str!("fixtures/runtime.redacted.log"));
+    }
+
+    #[test]
+    fn short_known_secrets_are_left_alone() {
+        assert_eq!(redact_text("id 12345", &["12345".to_string()]), "id 12345");
+    }
+
+    #[test]
+    fn json_values_are_redacted_by_key() {
+        let mut value = json!({
+            "username": "creator",
+            "Authorization": "Bearer abc",
+            "subscriptions": [{ "paymentMethodId": "1", "subscriberId": "2" }],
+            "nested": { "refreshToken": "xyz", "email": "fan@example.com" },
+        });
+        redact_value(&mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "username": "creator",
+                "Authorization": REDACTED,
+                "subscriptions": [{ "paymentMethodId": REDACTED, "subscriberId": "2" }],
+                "nested": { "refreshToken": REDACTED, "email": REDACTED },
+            })
+        );
+    }
+
+    #[test]
+    fn body_snippets_are_redacted_and_truncated() {
+        assert_eq!(
+            body_snippet(r#"{"token":"abc","id":"1"}"#, 100),
+            r#"{"id":"1","token":"[redacted]"}"#
+        );
+        assert_eq!(body_snippet("token=abc", 100), "<9 bytes of non-JSON body>");
+        assert!(body_snippet(r#"{"id":"1234567890"}"#, 5).starts_with(r#"{"id""#));
+    }
+}
@@ -0,0 +1,103 @@
+// Tracks whether auto-sync is allowed to run. The interval itself lives in the frontend,
+// every automatic sync has to get through ensure_started first.
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::sync::Mutex;
+
+use crate::handlers::fansly::{Fansly, FanslyError};
+use crate::handlers::time::now_ms;
+use crate::structs::SyncTokenInfo;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", content = "reason", rename_all = "snake_case")]
+pub enum SchedulerState {
+    #[default]
+    Idle,
+    Running,
+    Paused(String),
+}
+
+#[derive(Default, Debug, Clone, Serialize)]
+pub struct SchedulerStatus {
+    #[serde(flatten)]
+    pub state: SchedulerState,
+    pub token_info: Option<SyncTokenInfo>,
+    // The sync token we validated, so a changed token gets checked again
+    #[serde(skip)]
+    validated_token: Option<String>,
+}
+
+lazy_static! {
+    static ref SCHEDULER: Mutex<SchedulerStatus> = Mutex::new(SchedulerStatus::default());
+}
+
+pub fn status() -> SchedulerStatus {
+    SCHEDULER.lock().unwrap().clone()
+}
+
+pub fn pause(reason: String) {
+    log::warn!("[scheduler::pause] Pausing auto-sync: {}", reason);
+    SCHEDULER.lock().unwrap().state = SchedulerState::Paused(reason);
+}
+
+// Drop back to idle, the next automatic sync validates everything again
+pub fn reset() {
+    log::info!("[scheduler::reset] Resetting auto-sync scheduler");
+    *SCHEDULER.lock().unwrap() = SchedulerStatus::default();
+}
+
+// Make sure auto-sync may run with this sync token, validating it against the logged in account if needed
+pub async fn ensure_started(fansly: &Fansly, sync_token: &str) -> Result<(), FanslyError> {
+    {
+        let scheduler = SCHEDULER.lock().unwrap();
+        if let SchedulerState::Paused(reason) = &scheduler.state {
+            return Err(FanslyError::Other(format!(
+                "Auto-sync is paused: {}",
+                reason
+            )));
+        }
+
+        let token_expired = scheduler
+            .token_info
+            .as_ref()
+            .is_some_and(|info| info.is_expired(now_ms()));
+
+        if scheduler.state == SchedulerState::Running
+            && scheduler.validated_token.as_deref() == Some(sync_token)
+            && !token_expired
+        {
+            return Ok(());
+        }
+    }
+
+    log::info!("[scheduler::ensure_started] Validating sync token before starting auto-sync...");
+
+    let token_info = fansly.verify_sync_token_account(sync_token).await?;
+
+    if token_info.is_expired(now_ms()) {
+        log::error!(
+            "[scheduler::ensure_started] Sync token is expired, refusing to start auto-sync"
+        );
+        return Err(FanslyError::Other(
+            "Sync token has expired. Please generate a new one.".to_string(),
+        ));
+    }
+
+    log::info!("[scheduler::ensure_started] Sync token validated, auto-sync started");
+
+    let mut scheduler = SCHEDULER.lock().unwrap();
+
+    // Something may have paused us while we were talking to the APIs
+    if let SchedulerState::Paused(reason) = &scheduler.state {
+        return Err(FanslyError::Other(format!(
+            "Auto-sync is paused: {}",
+            reason
+        )));
+    }
+
+    scheduler.state = SchedulerState::Running;
+    scheduler.token_info = Some(token_info);
+    scheduler.validated_token = Some(sync_token.to_string());
+
+    Ok(())
+}
@@ -0,0 +1,313 @@
+// HMAC signing for payloads we send to the bot API, so the receiver can check they came from us untampered.
+// Every signed request carries three headers:
+//   X-FanslySync-Signature: "sha256=<lowercase hex HMAC-SHA256 of '<timestamp>.<nonce>.<body>'>"
+//   X-FanslySync-Timestamp: UNIX time in seconds when it was signed
+//   X-FanslySync-Nonce:     a random UUID, unique per request
+// The body is the canonical JSON sent as-is. Receivers recompute the signature, reject stale timestamps
+// and remember nonces to reject replays (see DEVELOPERS.md).
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SIGNATURE_HEADER: &str = "X-FanslySync-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-FanslySync-Timestamp";
+pub const NONCE_HEADER: &str = "X-FanslySync-Nonce";
+
+const SIGNATURE_PREFIX: &str = "sha256=";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedPayload {
+    pub body: String,
+    pub signature: String,
+    pub timestamp: u64,
+    pub nonce: String,
+}
+
+// Rebuild the value with object keys sorted so both sides serialize it the same way
+fn canonicalize(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+
+            let mut sorted = Map::new();
+            for key in keys {
+                sorted.insert(key.clone(), canonicalize(&map[key]));
+            }
+            Value::Object(sorted)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonicalize).collect()),
+        other => other.clone(),
+    }
+}
+
+pub fn canonical_json(value: &Value) -> String {
+    canonicalize(value).to_string()
+}
+
+fn mac_for(secret: &str, timestamp: u64, nonce: &str, body: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}.", timestamp, nonce).as_bytes());
+    mac.update(body.as_bytes());
+    mac
+}
+
+pub fn compute_signature(secret: &str, timestamp: u64, nonce: &str, body: &str) -> String {
+    let digest = mac_for(secret, timestamp, nonce, body)
+        .finalize()
+        .into_bytes();
+    format!("{}{}", SIGNATURE_PREFIX, hex::encode(digest))
+}
+
+// Check a signature header value against the timestamp, nonce and body it came with. The comparison is
+// constant time. Checking the timestamp is recent and the nonce unused is up to the receiver.
+#[allow(dead_code)] // The app only signs, this is the receiving side
+pub fn verify(secret: &str, timestamp: u64, nonce: &str, body: &str, signature: &str) -> bool {
+    let Some(digest) = signature
+        .strip_prefix(SIGNATURE_PREFIX)
+        .and_then(|digest| hex::decode(digest).ok())
+    else {
+        return false;
+    };
+
+    // verify_slice compares in constant time
+    mac_for(secret, timestamp, nonce, body)
+        .verify_slice(&digest)
+        .is_ok()
+}
+
+// Sign a JSON payload with a fresh timestamp and nonce. The returned body must be sent as-is.
+pub fn sign_payload(secret: &str, payload: &Value) -> SignedPayload {
+    let body = canonical_json(payload);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let nonce = uuid::Uuid::new_v4().to_string();
+    let signature = compute_signature(secret, timestamp, &nonce, &body);
+
+    SignedPayload {
+        body,
+        signature,
+        timestamp,
+        nonce,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SECRET: &str = "signing-secret";
+    const NOW: u64 = 1_721_988_883;
+    // What DEVELOPERS.md tells receivers to allow
+    const TOLERANCE_SECS: u64 = 300;
+
+    #[derive(Debug, PartialEq)]
+    enum Rejected {
+        Malformed,
+        Expired,
+        Mismatch,
+    }
+
+    // A receiver following DEVELOPERS.md, minus nonce tracking
+    fn receive(
+        secret: &str,
+        body: &str,
+        signature: &str,
+        timestamp: &str,
+        nonce: &str,
+        now: u64,
+    ) -> Result<(), Rejected> {
+        let timestamp: u64 = timestamp.parse().map_err(|_| Rejected::Malformed)?;
+        if nonce.is_empty() {
+            return Err(Rejected::Malformed);
+        }
+
+        let well_formed = signature
+            .strip_prefix(SIGNATURE_PREFIX)
+            .is_some_and(|digest| hex::decode(digest).is_ok());
+        if !well_formed {
+            return Err(Rejected::Malformed);
+        }
+
+        if now.abs_diff(timestamp) > TOLERANCE_SECS {
+            return Err(Rejected::Expired);
+        }
+
+        if verify(secret, timestamp, nonce, body, signature) {
+            Ok(())
+        } else {
+            Err(Rejected::Mismatch)
+        }
+    }
+
+    fn verify_signed(signed: &SignedPayload, secret: &str, now: u64) -> Result<(), Rejected> {
+        receive(
+            secret,
+            &signed.body,
+            &signed.signature,
+            &signed.timestamp.to_string(),
+            &signed.nonce,
+            now,
+        )
+    }
+
+    fn signed_at(timestamp: u64) -> SignedPayload {
+        let body = canonical_json(&json!({ "followers": [{ "followerId": "1" }] }));
+        let nonce = "nonce".to_string();
+        SignedPayload {
+            signature: compute_signature(SECRET, timestamp, &nonce, &body),
+            body,
+            timestamp,
+            nonce,
+        }
+    }
+
+    #[test]
+    fn canonical_json_sorts_keys_at_every_level() {
+        let a = json!({ "b": 1, "a": { "d": [ { "y": 1, "x": 2 } ], "c": null } });
+        let b: Value =
+            serde_json::from_str(r#"{"a":{"c":null,"d":[{"x":2,"y":1}]},"b":1}"#).unwrap();
+
+        assert_eq!(
+            canonical_json(&a),
+            r#"{"a":{"c":null,"d":[{"x":2,"y":1}]},"b":1}"#
+        );
+        assert_eq!(canonical_json(&a), canonical_json(&b));
+    }
+
+    #[test]
+    fn signed_payloads_verify() {
+        let payload = json!({ "subscribers": [], "followers": [{ "followerId": "1" }] });
+        let signed = sign_payload(SECRET, &payload);
+
+        assert_eq!(signed.body, canonical_json(&payload));
+        assert!(signed.signature.starts_with(SIGNATURE_PREFIX));
+        assert_eq!(verify_signed(&signed, SECRET, signed.timestamp), Ok(()));
+    }
+
+    #[test]
+    fn every_signature_gets_its_own_nonce() {
+        let payload = json!({ "followers": [] });
+        let first = sign_payload(SECRET, &payload);
+        let second = sign_payload(SECRET, &payload);
+
+        assert_ne!(first.nonce, second.nonce);
+        assert_ne!(first.signature, second.signature);
+    }
+
+    #[test]
+    fn tampered_bodies_are_rejected() {
+        let mut signed = signed_at(NOW);
+        signed.body = signed.body.replace("\"1\"", "\"2\"");
+
+        assert_eq!(verify_signed(&signed, SECRET, NOW), Err(Rejected::Mismatch));
+    }
+
+    #[test]
+    fn tampered_nonces_are_rejected() {
+        let mut signed = signed_at(NOW);
+        signed.nonce = "another-nonce".to_string();
+
+        assert_eq!(verify_signed(&signed, SECRET, NOW), Err(Rejected::Mismatch));
+    }
+
+    #[test]
+    fn a_wrong_secret_is_rejected() {
+        assert_eq!(
+            verify_signed(&signed_at(NOW), "another-secret", NOW),
+            Err(Rejected::Mismatch)
+        );
+    }
+
+    #[test]
+    fn stale_and_future_timestamps_are_rejected() {
+        assert_eq!(
+            verify_signed(&signed_at(NOW - TOLERANCE_SECS), SECRET, NOW),
+            Ok(())
+        );
+        assert_eq!(
+            verify_signed(&signed_at(NOW - TOLERANCE_SECS - 1), SECRET, NOW),
+            Err(Rejected::Expired)
+        );
+        assert_eq!(
+            verify_signed(&signed_at(NOW + TOLERANCE_SECS + 1), SECRET, NOW),
+            Err(Rejected::Expired)
+        );
+    }
+
+    #[test]
+    fn verify_checks_every_signed_part() {
+        let signed = signed_at(NOW);
+
+        assert!(verify(
+            SECRET,
+            NOW,
+            "nonce",
+            &signed.body,
+            &signed.signature
+        ));
+        assert!(!verify(
+            "another-secret",
+            NOW,
+            "nonce",
+            &signed.body,
+            &signed.signature
+        ));
+        assert!(!verify(
+            SECRET,
+            NOW + 1,
+            "nonce",
+            &signed.body,
+            &signed.signature
+        ));
+        assert!(!verify(
+            SECRET,
+            NOW,
+            "other",
+            &signed.body,
+            &signed.signature
+        ));
+        assert!(!verify(SECRET, NOW, "nonce", "{}", &signed.signature));
+        assert!(!verify(SECRET, NOW, "nonce", &signed.body, "sha256=zz"));
+        assert!(!verify(
+            SECRET,
+            NOW,
+            "nonce",
+            &signed.body,
+            signed.signature.trim_start_matches(SIGNATURE_PREFIX)
+        ));
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let signed = signed_at(NOW);
+        let digest = signed.signature.trim_start_matches(SIGNATURE_PREFIX);
+        let timestamp = NOW.to_string();
+
+        for (signature, timestamp, nonce) in [
+            (digest, timestamp.as_str(), "nonce"),
+            ("sha1=abcd", timestamp.as_str(), "nonce"),
+            ("sha256=not-hex", timestamp.as_str(), "nonce"),
+            ("", timestamp.as_str(), "nonce"),
+            (signed.signature.as_str(), "yesterday", "nonce"),
+            (signed.signature.as_str(), timestamp.as_str(), ""),
+        ] {
+            assert_eq!(
+                receive(SECRET, &signed.body, signature, timestamp, nonce, NOW),
+                Err(Rejected::Malformed),
+                "{:?} {:?} {:?}",
+                signature,
+                timestamp,
+                nonce
+            );
+        }
+    }
+}
@@ -0,0 +1,10 @@
+// Timestamps as we store and send them
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The current UNIX time in milliseconds, 0 if the clock is set before 1970
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,328 @@
+// Outgoing webhooks for our own integrations. Every delivery is a JSON body of the form
+// {"event": "...", "timestamp": <ms>, "data": {...}}, signed like bot API uploads when the hook has a secret.
+use lazy_static::lazy_static;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::handlers::delta::SyncDelta;
+use crate::handlers::signing::{self, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use crate::handlers::time::now_ms;
+
+pub const EVENT_HEADER: &str = "X-FanslySync-Event";
+
+const MAX_ATTEMPTS: u32 = 3;
+// How many deliveries we remember for the delivery log
+const MAX_LOGGED_DELIVERIES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    #[serde(rename = "sync.started")]
+    SyncStarted,
+    #[serde(rename = "sync.completed")]
+    SyncCompleted,
+    #[serde(rename = "sync.failed")]
+    SyncFailed,
+    #[serde(rename = "subscriber.new")]
+    NewSubscribers,
+    #[serde(rename = "subscriber.cancelled")]
+    Cancellations,
+    #[serde(rename = "subscriber.expired")]
+    Expirations,
+    #[serde(rename = "subscriber.tier_changed")]
+    TierChanges,
+    #[serde(rename = "follower.new")]
+    NewFollowers,
+    #[serde(rename = "follower.lost")]
+    LostFollowers,
+    #[serde(rename = "milestone.reached")]
+    Milestone,
+}
+
+impl WebhookEvent {
+    // The name receivers see, the same one the event is saved under in the config
+    pub fn name(self) -> String {
+        match serde_json::to_value(self) {
+            Ok(Value::String(name)) => name,
+            _ => unreachable!("webhook events serialize to their name"),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Signs deliveries when set, receivers verify them as described in DEVELOPERS.md
+    #[serde(default)]
+    pub secret: String,
+    // Events this hook wants, empty means all of them
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+impl WebhookConfig {
+    fn wants(&self, event: WebhookEvent) -> bool {
+        self.enabled && (self.events.is_empty() || self.events.contains(&event))
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryRecord {
+    // Only the host, webhook URLs often have their credentials in the path
+    pub host: String,
+    pub event: String,
+    pub timestamp: i64,
+    pub attempts: u32,
+    pub status: Option<u16>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+lazy_static! {
+    static ref DELIVERIES: Mutex<VecDeque<DeliveryRecord>> = Mutex::new(VecDeque::new());
+}
+
+pub fn url_host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "<invalid url>".to_string())
+}
+
+fn log_delivery(record: DeliveryRecord) {
+    let mut deliveries = DELIVERIES.lock().unwrap();
+    if deliveries.len() >= MAX_LOGGED_DELIVERIES {
+        deliveries.pop_front();
+    }
+    deliveries.push_back(record);
+}
+
+// Most recent deliveries, newest first
+pub fn deliveries() -> Vec<DeliveryRecord> {
+    DELIVERIES.lock().unwrap().iter().rev().cloned().collect()
+}
+
+// The webhook events a sync delta produces, one per kind of change with all of its items
+pub fn delta_events(delta: &SyncDelta) -> Vec<(WebhookEvent, Value)> {
+    let mut events = Vec::new();
+
+    if !delta.new_subscribers.is_empty() {
+        events.push((
+            WebhookEvent::NewSubscribers,
+            json!({ "subscribers": delta.new_subscribers }),
+        ));
+    }
+    if !delta.cancellations.is_empty() {
+        events.push((
+            WebhookEvent::Cancellations,
+            json!({ "subscribers": delta.cancellations }),
+        ));
+    }
+    if !delta.expirations.is_empty() {
+        events.push((
+            WebhookEvent::Expirations,
+            json!({ "subscribers": delta.expirations }),
+        ));
+    }
+    if !delta.tier_changes.is_empty() {
+        events.push((
+            WebhookEvent::TierChanges,
+            json!({ "changes": delta.tier_changes }),
+        ));
+    }
+    if !delta.new_followers.is_empty() {
+        events.push((
+            WebhookEvent::NewFollowers,
+            json!({ "followers": delta.new_followers }),
+        ));
+    }
+    if !delta.lost_followers.is_empty() {
+        events.push((
+            WebhookEvent::LostFollowers,
+            json!({ "followers": delta.lost_followers }),
+        ));
+    }
+    if delta.follower_milestone.is_some() || delta.subscriber_milestone.is_some() {
+        events.push((
+            WebhookEvent::Milestone,
+            json!({
+                "followers": delta.follower_milestone,
+                "subscribers": delta.subscriber_milestone,
+            }),
+        ));
+    }
+
+    events
+}
+
+// Server errors and rate limits are worth another try, anything else the receiver rejected for good
+fn should_retry(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn request_parts(
+    hook: &WebhookConfig,
+    event: WebhookEvent,
+    payload: &Value,
+) -> (HeaderMap, String) {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert(EVENT_HEADER, event.name().parse().unwrap());
+
+    if hook.secret.is_empty() {
+        return (headers, signing::canonical_json(payload));
+    }
+
+    // A fresh timestamp and nonce per attempt, so retries don't look like replays
+    let signed = signing::sign_payload(&hook.secret, payload);
+    headers.insert(SIGNATURE_HEADER, signed.signature.parse().unwrap());
+    headers.insert(
+        TIMESTAMP_HEADER,
+        signed.timestamp.to_string().parse().unwrap(),
+    );
+    headers.insert(NONCE_HEADER, signed.nonce.parse().unwrap());
+
+    (headers, signed.body)
+}
+
+// POST to a URL, retrying as needed, and add the outcome to the delivery log.
+// `request` builds the headers and body for each attempt.
+pub async fn deliver(
+    client: &reqwest::Client,
+    url: &str,
+    event: &str,
+    request: impl Fn() -> (HeaderMap, String),
+) {
+    let mut record = DeliveryRecord {
+        host: url_host(url),
+        event: event.to_string(),
+        timestamp: now_ms(),
+        ..Default::default()
+    };
+
+    while record.attempts < MAX_ATTEMPTS {
+        if record.attempts > 0 {
+            // 1s, 2s, 4s, ...
+            tokio::time::sleep(Duration::from_secs(1 << (record.attempts - 1))).await;
+        }
+        record.attempts += 1;
+
+        let (headers, body) = request();
+        match client.post(url).headers(headers).body(body).send().await {
+            Ok(response) => {
+                let status = response.status();
+                record.status = Some(status.as_u16());
+                record.success = status.is_success();
+                record.error = (!record.success).then(|| status.to_string());
+
+                if record.success || !should_retry(status) {
+                    break;
+                }
+            }
+            Err(e) => {
+                record.status = None;
+                record.error = Some(e.without_url().to_string());
+            }
+        }
+    }
+
+    if record.success {
+        log::info!(
+            "[webhooks::deliver] Delivered {} to {} after {} attempt(s)",
+            record.event,
+            record.host,
+            record.attempts
+        );
+    } else {
+        log::error!(
+            "[webhooks::deliver] Failed to deliver {} to {} after {} attempt(s): {}",
+            record.event,
+            record.host,
+            record.attempts,
+            record.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+
+    log_delivery(record);
+}
+
+// Send an event to every hook that wants it. Hooks are delivered to one after another.
+pub async fn fire(
+    client: &reqwest::Client,
+    hooks: &[WebhookConfig],
+    event: WebhookEvent,
+    data: Value,
+) {
+    let name = event.name();
+    let payload = json!({
+        "event": name,
+        "timestamp": now_ms(),
+        "data": data,
+    });
+
+    for hook in hooks.iter().filter(|hook| hook.wants(event)) {
+        deliver(client, &hook.url, &name, || {
+            request_parts(hook, event, &payload)
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::delta::tests::{default_sync, subscription};
+    use crate::structs::{AutoRenew, SubscriptionStatus};
+
+    #[test]
+    fn default_syncs_send_cancellations() {
+        let previous = default_sync(
+            1,
+            vec![subscription("a", SubscriptionStatus::Active, AutoRenew::On)],
+        );
+        let current = default_sync(
+            2,
+            vec![subscription(
+                "a",
+                SubscriptionStatus::Active,
+                AutoRenew::Off,
+            )],
+        );
+
+        let events = delta_events(&SyncDelta::between(&previous, &current));
+        assert_eq!(events.len(), 1);
+
+        let (event, data) = &events[0];
+        assert_eq!(event.name(), "subscriber.cancelled");
+        assert_eq!(data["subscribers"][0]["subscriberId"], "a");
+    }
+
+    #[test]
+    fn hooks_only_get_the_events_they_want() {
+        let hook = WebhookConfig {
+            url: "https://example.com/hook".to_string(),
+            events: vec![WebhookEvent::Cancellations],
+            enabled: true,
+            ..Default::default()
+        };
+        assert!(hook.wants(WebhookEvent::Cancellations));
+        assert!(!hook.wants(WebhookEvent::Expirations));
+
+        let disabled = WebhookConfig {
+            enabled: false,
+            ..hook
+        };
+        assert!(!disabled.wants(WebhookEvent::Cancellations));
+    }
+}
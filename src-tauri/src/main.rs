@@ -5,31 +5,34 @@ mod commands;
 mod handlers;
 mod structs;
 
-use std::fs;
 use std::io;
 
-use commands::config::{get_config, init_config, save_config};
+use commands::analytics::{
+    analytics_churn, analytics_cohorts, analytics_forecast, analytics_tier_report,
+};
+use commands::config::{
+    export_config, get_config, import_config, init_config, save_config, update_config,
+    validate_config,
+};
+use commands::diagnostics::create_diagnostics_bundle;
 use commands::fansly::{
-    fansly_check_sync_token, fansly_get_me, fansly_set_token, fansly_sync,
-    fansly_upload_auto_sync_data,
+    fansly_check_sync_token, fansly_get_earnings, fansly_get_me, fansly_get_token_status,
+    fansly_set_token, fansly_sync, fansly_upload_auto_sync_data, fansly_validate_token,
 };
+use commands::scheduler::{scheduler_get_status, scheduler_reset};
 use commands::utils::quit;
+use commands::webhooks::webhooks_get_deliveries;
+use handlers::diagnostics::LOG_FILE_STEM;
+use handlers::logging::init_json_log;
+use handlers::overrides::overrides;
 use tauri_plugin_autostart::MacosLauncher;
 use tauri_plugin_log::{Target, TargetKind};
 
 fn get_log_path() -> io::Result<String> {
-    let mut config_dir = dirs::config_dir().ok_or_else(|| {
-        io::Error::new(
-            io::ErrorKind::NotFound,
-            "Could not determine user's config directory",
-        )
-    })?;
-    config_dir.push("FanslySync");
-    fs::create_dir_all(&config_dir)?;
-    config_dir.push("runtime");
+    let log_path = overrides().data_dir()?.join(LOG_FILE_STEM);
 
     // Return the path as a string
-    Ok(config_dir.to_string_lossy().to_string())
+    Ok(log_path.to_string_lossy().to_string())
 }
 
 #[tokio::main]
@@ -55,18 +58,42 @@ async fn main() {
                 ])
                 .rotation_strategy(tauri_plugin_log::RotationStrategy::KeepOne)
                 .max_file_size(1024 * 1024 * 5)
+                // Span enter/exit records from tracing, the JSON log has the spans already
+                .filter(|metadata| !metadata.target().starts_with("tracing::span"))
                 .build(),
         )
+        .setup(|_app| {
+            // After the log plugin, so a broken config still gets logged
+            if let Err(e) = init_json_log() {
+                tracing::error!(error = %e, "Failed to start JSON logging");
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             init_config,
             get_config,
             save_config,
+            update_config,
+            validate_config,
+            export_config,
+            import_config,
             quit,
             fansly_set_token,
             fansly_get_me,
             fansly_sync,
             fansly_upload_auto_sync_data,
-            fansly_check_sync_token
+            fansly_check_sync_token,
+            fansly_validate_token,
+            fansly_get_token_status,
+            fansly_get_earnings,
+            analytics_tier_report,
+            analytics_churn,
+            analytics_cohorts,
+            analytics_forecast,
+            scheduler_get_status,
+            scheduler_reset,
+            webhooks_get_deliveries,
+            create_diagnostics_bundle
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
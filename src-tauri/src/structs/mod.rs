@@ -1,13 +1,234 @@
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use crate::handlers::analytics::tiers::TierReport;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SyncDataResponse {
     pub followers: Vec<FanslyFollowersResponse>,
     pub subscribers: Vec<Subscription>,
+    // Only present when the sync was asked to fetch them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub following: Option<Vec<FanslyFollowingResponse>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lists: Option<Vec<FanslyList>>,
+    // Account details for follower and subscriber ids, keyed by account id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accounts: Option<BTreeMap<String, AccountSummary>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earnings: Option<EarningsData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier_report: Option<TierReport>,
+    // Totals from the profile at sync time
+    #[serde(default)]
+    pub follow_count: i64,
+    #[serde(default)]
+    pub subscriber_count: i64,
     pub sync_data_url: String,
 }
 
+// How much of each subscription we share when uploading sync data.
+// Full sends everything Fansly gave us, Standard drops payment and promo details,
+// Minimal only sends ids, statuses and dates.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadProfile {
+    #[default]
+    Full,
+    Standard,
+    Minimal,
+}
+
+// Fansly sends these as plain integers. They are (de)serialized as the same integers so
+// the payloads we upload keep the shape documented in DEVELOPERS.md.
+// 3 and 4 are what the subscribers endpoint returns for active and expired subscriptions. We haven't
+// confirmed what any other code means, so those are kept as they are in Unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum SubscriptionStatus {
+    Active,
+    Expired,
+    Unknown(i64),
+}
+
+impl Default for SubscriptionStatus {
+    fn default() -> Self {
+        SubscriptionStatus::Unknown(0)
+    }
+}
+
+impl From<i64> for SubscriptionStatus {
+    fn from(value: i64) -> Self {
+        match value {
+            3 => SubscriptionStatus::Active,
+            4 => SubscriptionStatus::Expired,
+            other => SubscriptionStatus::Unknown(other),
+        }
+    }
+}
+
+impl From<SubscriptionStatus> for i64 {
+    fn from(status: SubscriptionStatus) -> Self {
+        match status {
+            SubscriptionStatus::Active => 3,
+            SubscriptionStatus::Expired => 4,
+            SubscriptionStatus::Unknown(other) => other,
+        }
+    }
+}
+
+// Billing cycles are sent as a number of days
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum BillingCycle {
+    Monthly,
+    Quarterly,
+    SemiAnnually,
+    Yearly,
+    Custom(i64),
+}
+
+impl Default for BillingCycle {
+    fn default() -> Self {
+        BillingCycle::Custom(0)
+    }
+}
+
+impl From<i64> for BillingCycle {
+    fn from(days: i64) -> Self {
+        match days {
+            30 => BillingCycle::Monthly,
+            90 => BillingCycle::Quarterly,
+            180 => BillingCycle::SemiAnnually,
+            365 => BillingCycle::Yearly,
+            other => BillingCycle::Custom(other),
+        }
+    }
+}
+
+impl From<BillingCycle> for i64 {
+    fn from(cycle: BillingCycle) -> Self {
+        match cycle {
+            BillingCycle::Monthly => 30,
+            BillingCycle::Quarterly => 90,
+            BillingCycle::SemiAnnually => 180,
+            BillingCycle::Yearly => 365,
+            BillingCycle::Custom(days) => days,
+        }
+    }
+}
+
+impl BillingCycle {
+    pub fn days(self) -> i64 {
+        self.into()
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum AutoRenew {
+    #[default]
+    Off,
+    On,
+    Unknown(i64),
+}
+
+impl From<i64> for AutoRenew {
+    fn from(value: i64) -> Self {
+        match value {
+            0 => AutoRenew::Off,
+            1 => AutoRenew::On,
+            other => AutoRenew::Unknown(other),
+        }
+    }
+}
+
+impl From<AutoRenew> for i64 {
+    fn from(auto_renew: AutoRenew) -> Self {
+        match auto_renew {
+            AutoRenew::Off => 0,
+            AutoRenew::On => 1,
+            AutoRenew::Unknown(other) => other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum PlanStatus {
+    Disabled,
+    Active,
+    Unknown(i64),
+}
+
+impl Default for PlanStatus {
+    fn default() -> Self {
+        PlanStatus::Unknown(0)
+    }
+}
+
+impl From<i64> for PlanStatus {
+    fn from(value: i64) -> Self {
+        match value {
+            0 => PlanStatus::Disabled,
+            1 => PlanStatus::Active,
+            other => PlanStatus::Unknown(other),
+        }
+    }
+}
+
+impl From<PlanStatus> for i64 {
+    fn from(status: PlanStatus) -> Self {
+        match status {
+            PlanStatus::Disabled => 0,
+            PlanStatus::Active => 1,
+            PlanStatus::Unknown(other) => other,
+        }
+    }
+}
+
+// An amount of money as the API sends it, in cents
+#[derive(
+    Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Money(i64);
+
+impl Money {
+    pub fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    pub fn cents(self) -> i64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cents = self.cents();
+        let sign = if cents < 0 { "-" } else { "" };
+        write!(f, "{}${}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::default(), Add::add)
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FanslyBaseResponse<T> {
@@ -28,18 +249,102 @@ pub struct FanslyFollowersResponse {
     pub follower_id: String,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FanslyFollowingResponse {
+    pub account_id: String,
+}
+
+// One of the creator's custom lists. Members are fetched separately and filled in by us.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FanslyList {
+    pub id: String,
+    #[serde(default, alias = "label", deserialize_with = "null_as_default")]
+    pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub item_count: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub created_at: i64,
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FanslyListItem {
+    #[serde(alias = "accountId")]
+    pub item_id: String,
+}
+
+// An account as returned by the account lookup endpoint, we only keep what we need
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FanslyAccountLookup {
+    pub id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub username: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub created_at: i64,
+    #[serde(default)]
+    pub avatar: Option<MediaRef>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaRef {
+    pub id: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountSummary {
+    pub id: String,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub created_at: i64,
+    pub avatar_id: Option<String>,
+}
+
+impl From<FanslyAccountLookup> for AccountSummary {
+    fn from(account: FanslyAccountLookup) -> Self {
+        AccountSummary {
+            id: account.id,
+            username: account.username,
+            display_name: account.display_name,
+            created_at: account.created_at,
+            avatar_id: account.avatar.map(|avatar| avatar.id),
+        }
+    }
+}
+
+// Username if the sync looked accounts up, otherwise the account id
+pub fn display_name(id: &str, accounts: Option<&BTreeMap<String, AccountSummary>>) -> String {
+    accounts
+        .and_then(|accounts| accounts.get(id))
+        .map(|account| format!("@{}", account.username))
+        .unwrap_or_else(|| id.to_string())
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FanslySubscriptionsResponse {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub stats: SubscriptionsStats,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub subscriptions: Vec<Subscription>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscriptionsStats {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub total_active: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub total_expired: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub total: i64,
 }
 
@@ -47,39 +352,69 @@ pub struct SubscriptionsStats {
 #[serde(rename_all = "camelCase")]
 pub struct Subscription {
     pub id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub history_id: String,
     pub subscriber_id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub subscription_tier_id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub subscription_tier_name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub subscription_tier_color: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub plan_id: String,
+    #[serde(default, deserialize_with = "optional_id")]
     pub promo_id: Option<String>,
+    #[serde(default)]
     pub gift_code_id: Value,
-    pub payment_method_id: String,
-    pub status: i64,
-    pub price: i64,
-    pub renew_price: i64,
-    pub renew_correlation_id: String,
-    pub auto_renew: i64,
-    pub billing_cycle: i64,
+    #[serde(default)]
+    pub payment_method_id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub status: SubscriptionStatus,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub price: Money,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub renew_price: Money,
+    #[serde(default)]
+    pub renew_correlation_id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub auto_renew: AutoRenew,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub billing_cycle: BillingCycle,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub duration: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub renew_date: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub version: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub created_at: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub updated_at: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub ends_at: i64,
+    #[serde(default)]
     pub promo_price: Value,
+    #[serde(default)]
     pub promo_duration: Value,
+    #[serde(default)]
     pub promo_status: Value,
+    #[serde(default)]
     pub promo_starts_at: Value,
+    #[serde(default)]
     pub promo_ends_at: Value,
+    // Anything Fansly sends that we don't know about yet, passed through untouched
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FanslyAccountResponse {
     pub account: Account,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub correlation_id: String,
+    #[serde(default)]
     pub check_token: Value,
 }
 
@@ -87,51 +422,85 @@ pub struct FanslyAccountResponse {
 #[serde(rename_all = "camelCase")]
 pub struct Account {
     pub id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub email: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub username: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub display_name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub flags: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub version: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub created_at: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub follow_count: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub subscriber_count: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub permissions: Permissions,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub timeline_stats: TimelineStats,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub profile_access_flags: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub profile_flags: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub about: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub location: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub profile_socials: Vec<Value>,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub status_id: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub last_seen_at: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub post_likes: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub streaming: Streaming,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub account_media_likes: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub subscription_tiers: Vec<SubscriptionTier>,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub profile_access: bool,
+    // Anything Fansly sends that we don't know about yet, passed through untouched
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Permissions {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub account_permission_flags: AccountPermissionFlags,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AccountPermissionFlags {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub flags: i64,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TimelineStats {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub account_id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub image_count: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub video_count: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub bundle_count: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub bundle_image_count: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub bundle_video_count: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub fetched_at: i64,
 }
 
@@ -139,45 +508,609 @@ pub struct TimelineStats {
 #[serde(rename_all = "camelCase")]
 pub struct MainWallet {
     pub id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub account_id: String,
-    pub balance: i64,
-    #[serde(rename = "type")]
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub balance: Money,
+    #[serde(rename = "type", default, deserialize_with = "null_as_default")]
     pub type_field: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub wallet_version: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub flags: i64,
+    // Only some wallets say which currency they hold, amounts are cents of it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+}
+
+// What a wallet transaction was for. Type ids are the ones the creator earnings page appears to use,
+// we haven't verified them against Fansly. Anything we don't recognize is kept as Unknown and counted
+// under "other".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum TransactionKind {
+    Subscription,
+    Tip,
+    Ppv,
+    MediaSale,
+    Unknown(i64),
+}
+
+impl Default for TransactionKind {
+    fn default() -> Self {
+        TransactionKind::Unknown(0)
+    }
+}
+
+impl From<i64> for TransactionKind {
+    fn from(value: i64) -> Self {
+        match value {
+            1 => TransactionKind::Subscription,
+            2 => TransactionKind::Tip,
+            3 => TransactionKind::Ppv,
+            4 => TransactionKind::MediaSale,
+            other => TransactionKind::Unknown(other),
+        }
+    }
+}
+
+impl From<TransactionKind> for i64 {
+    fn from(kind: TransactionKind) -> Self {
+        match kind {
+            TransactionKind::Subscription => 1,
+            TransactionKind::Tip => 2,
+            TransactionKind::Ppv => 3,
+            TransactionKind::MediaSale => 4,
+            TransactionKind::Unknown(other) => other,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WalletTransaction {
+    pub id: String,
+    #[serde(rename = "type", default, deserialize_with = "null_as_default")]
+    pub kind: TransactionKind,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub amount: Money,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub fee: Money,
+    #[serde(default)]
+    pub sender_id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub created_at: i64,
+}
+
+// Earnings for a date range, broken down by what they were for. Amounts are net of fees.
+// Transactions don't say which currency they are in, so the combined balance and total are only
+// given when the wallets agree on one.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EarningsSummary {
+    pub start: i64,
+    pub end: i64,
+    // The wallets' currency, if any of them say and they all agree
+    pub currency: Option<String>,
+    pub balance: Option<Money>,
+    pub balances: BTreeMap<String, Money>,
+    // Wallets that don't say which currency they hold are kept out of `balances`
+    pub unlabelled_balance: Money,
+    pub total: Option<Money>,
+    pub subscriptions: Money,
+    pub tips: Money,
+    pub ppv: Money,
+    pub media_sales: Money,
+    pub other: Money,
+    // Net per raw transaction type, the ids behind the buckets above are unverified
+    pub by_type: BTreeMap<i64, Money>,
+    pub transaction_count: usize,
+}
+
+impl EarningsSummary {
+    pub fn from_transactions(
+        start: i64,
+        end: i64,
+        wallets: &[MainWallet],
+        transactions: &[WalletTransaction],
+    ) -> Self {
+        let mut summary = EarningsSummary {
+            start,
+            end,
+            transaction_count: transactions.len(),
+            ..Default::default()
+        };
+
+        for wallet in wallets {
+            match &wallet.currency {
+                Some(currency) => {
+                    let balance = summary.balances.entry(currency.clone()).or_default();
+                    *balance = *balance + wallet.balance;
+                }
+                None => summary.unlabelled_balance = summary.unlabelled_balance + wallet.balance,
+            }
+        }
+
+        let mut total = Money::default();
+        for transaction in transactions {
+            let net = Money::from_cents(transaction.amount.cents() - transaction.fee.cents());
+            let bucket = match transaction.kind {
+                TransactionKind::Subscription => &mut summary.subscriptions,
+                TransactionKind::Tip => &mut summary.tips,
+                TransactionKind::Ppv => &mut summary.ppv,
+                TransactionKind::MediaSale => &mut summary.media_sales,
+                TransactionKind::Unknown(_) => &mut summary.other,
+            };
+            *bucket = *bucket + net;
+
+            let by_type = summary.by_type.entry(transaction.kind.into()).or_default();
+            *by_type = *by_type + net;
+            total = total + net;
+        }
+
+        // Amounts in different currencies can't be added up
+        match summary.balances.len() {
+            0 => {
+                summary.balance = Some(summary.unlabelled_balance);
+                summary.total = Some(total);
+            }
+            1 => {
+                let (currency, balance) = summary.balances.iter().next().unwrap();
+                summary.currency = Some(currency.clone());
+                summary.balance = Some(*balance);
+                summary.total = Some(total);
+            }
+            _ => {}
+        }
+
+        summary
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EarningsData {
+    pub wallets: Vec<MainWallet>,
+    pub summary: EarningsSummary,
+    pub transactions: Vec<WalletTransaction>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Streaming {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub account_id: String,
+    #[serde(default)]
     pub channel: Value,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub enabled: bool,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscriptionTier {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub account_id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub color: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub pos: i64,
-    pub price: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub price: Money,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub max_subscribers: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub subscription_benefits: Vec<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub included_tier_ids: Vec<Value>,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub plans: Vec<Plan>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Plan {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub id: String,
-    pub status: i64,
-    pub billing_cycle: i64,
-    pub price: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub status: PlanStatus,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub billing_cycle: BillingCycle,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub price: Money,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub use_amounts: i64,
-    pub promos: Vec<Value>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub promos: Vec<Promo>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub uses: i64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Promo {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub status: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub price: Money,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub duration: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub max_uses: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub new_subscribers_only: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub starts_at: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub ends_at: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub uses: i64,
 }
+
+// Treats a null (or a missing field, together with #[serde(default)]) as the type's default value,
+// so Fansly nulling a field we don't depend on doesn't fail the whole sync
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// Accepts a string or a number (ids sometimes come back as either) and normalizes it to a string
+fn lenient_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) if !s.is_empty() => Some(s),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+// Fansly sends "0" (and sometimes "") for ids that aren't set, e.g. a subscription without a promo
+fn optional_id<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(lenient_string(deserializer)?.filter(|id| id != "0"))
+}
+
+// Accepts a number or a numeric string
+fn lenient_i64<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64)),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    })
+}
+
+// Accepts a list of strings or a single comma separated string
+fn lenient_string_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Array(items) => items
+            .into_iter()
+            .filter_map(|item| match item {
+                Value::String(s) => Some(s),
+                Value::Null => None,
+                other => Some(other.to_string()),
+            })
+            .collect(),
+        Value::String(s) => s
+            .split(',')
+            .map(|part| part.trim().to_string())
+            .filter(|part| !part.is_empty())
+            .collect(),
+        _ => Vec::new(),
+    })
+}
+
+// Plan limits as an object, or just the plan's name. Anything else is ignored rather than failing the whole reply.
+fn lenient_plan_limits<'de, D>(deserializer: D) -> Result<Option<BotPlanLimits>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        limits @ Value::Object(_) => serde_json::from_value(limits).ok(),
+        Value::String(name) if !name.is_empty() => Some(BotPlanLimits {
+            name: Some(name),
+            ..Default::default()
+        }),
+        _ => None,
+    })
+}
+
+// What the bot API tells us about a sync token. Every field is optional since the
+// bot has changed this shape before, and we would rather show partial info than fail.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SyncTokenInfo {
+    #[serde(
+        alias = "fanslyId",
+        alias = "fanslyAccountId",
+        alias = "account_id",
+        deserialize_with = "lenient_string"
+    )]
+    pub account_id: Option<String>,
+    #[serde(
+        alias = "fanslyUsername",
+        alias = "accountUsername",
+        deserialize_with = "lenient_string"
+    )]
+    pub username: Option<String>,
+    // UNIX timestamp, the bot has sent both seconds and milliseconds
+    #[serde(
+        alias = "expiry",
+        alias = "expires",
+        alias = "expires_at",
+        deserialize_with = "lenient_i64"
+    )]
+    pub expires_at: Option<i64>,
+    #[serde(alias = "scopes", deserialize_with = "lenient_string_list")]
+    pub permissions: Vec<String>,
+    #[serde(
+        alias = "plan",
+        alias = "limits",
+        alias = "planLimits",
+        deserialize_with = "lenient_plan_limits"
+    )]
+    pub plan_limits: Option<BotPlanLimits>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BotPlanLimits {
+    #[serde(
+        alias = "plan",
+        alias = "planName",
+        deserialize_with = "lenient_string"
+    )]
+    pub name: Option<String>,
+    #[serde(deserialize_with = "lenient_i64")]
+    pub max_followers: Option<i64>,
+    #[serde(deserialize_with = "lenient_i64")]
+    pub max_subscribers: Option<i64>,
+    #[serde(alias = "syncsPerDay", deserialize_with = "lenient_i64")]
+    pub max_syncs_per_day: Option<i64>,
+}
+
+impl SyncTokenInfo {
+    // Parse a raw checkSyncToken reply, unwrapping the usual { success, response } / { data } envelopes.
+    // Fields with unexpected values are left empty, but a reply that isn't an object at all is an error.
+    pub fn from_value(value: Value) -> Result<Self, String> {
+        let inner = match value {
+            Value::Object(mut map) => match map.remove("response").or_else(|| map.remove("data")) {
+                Some(inner @ Value::Object(_)) => inner,
+                Some(_) | None => Value::Object(map),
+            },
+            _ => return Err("Sync token info is not a JSON object".to_string()),
+        };
+
+        serde_json::from_value(inner).map_err(|e| format!("Could not read sync token info: {}", e))
+    }
+
+    // Expiry normalized to milliseconds
+    pub fn expires_at_ms(&self) -> Option<i64> {
+        self.expires_at.map(|expiry| {
+            if expiry < 100_000_000_000 {
+                expiry * 1000
+            } else {
+                expiry
+            }
+        })
+    }
+
+    pub fn is_expired(&self, now_ms: i64) -> bool {
+        self.expires_at_ms().is_some_and(|expiry| expiry <= now_ms)
+    }
+
+    // Check that the token belongs to the given Fansly account. A token that doesn't say which
+    // account it belongs to can't be checked, so it is refused rather than trusted.
+    pub fn ensure_linked_to(&self, account: &Account) -> Result<(), String> {
+        let Some(account_id) = &self.account_id else {
+            return Err(format!(
+                "Could not verify that the sync token belongs to {}, the bot didn't say which Fansly account it is linked to. Please generate a new sync token.",
+                account.username
+            ));
+        };
+
+        if account_id != &account.id {
+            return Err(format!(
+                "Sync token is linked to Fansly account {} but you are logged in as {} ({}). Please use the sync token for this account.",
+                self.username.as_deref().unwrap_or(account_id),
+                account.username,
+                account.id
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sync_token_info_unwraps_envelopes() {
+        let info = SyncTokenInfo::from_value(json!({
+            "success": true,
+            "response": { "fanslyId": 123, "scopes": "sync, read", "expiry": 1721988883 }
+        }))
+        .unwrap();
+
+        assert_eq!(info.account_id.as_deref(), Some("123"));
+        assert_eq!(info.permissions, vec!["sync", "read"]);
+        assert_eq!(info.expires_at_ms(), Some(1_721_988_883_000));
+    }
+
+    #[test]
+    fn odd_sub_fields_dont_lose_the_account() {
+        let info = SyncTokenInfo::from_value(json!({ "accountId": "1", "plan": "free" })).unwrap();
+        assert_eq!(info.account_id.as_deref(), Some("1"));
+        assert_eq!(
+            info.plan_limits.and_then(|limits| limits.name).as_deref(),
+            Some("free")
+        );
+
+        let info = SyncTokenInfo::from_value(json!({
+            "accountId": "1",
+            "limits": 5,
+            "expiresAt": { "seconds": 1 },
+            "permissions": true,
+        }))
+        .unwrap();
+        assert_eq!(info.account_id.as_deref(), Some("1"));
+        assert_eq!(info.plan_limits, None);
+        assert_eq!(info.expires_at, None);
+        assert!(info.permissions.is_empty());
+    }
+
+    #[test]
+    fn sync_tokens_must_name_the_logged_in_account() {
+        let account = Account {
+            id: "1".to_string(),
+            username: "creator".to_string(),
+            ..Default::default()
+        };
+        let info = |reply: Value| SyncTokenInfo::from_value(reply).unwrap();
+
+        assert!(info(json!({ "accountId": "1" }))
+            .ensure_linked_to(&account)
+            .is_ok());
+        assert!(info(json!({ "accountId": "2", "username": "creator" }))
+            .ensure_linked_to(&account)
+            .is_err());
+        // Nothing to compare against, a matching username alone isn't enough
+        assert!(info(json!({ "username": "creator" }))
+            .ensure_linked_to(&account)
+            .is_err());
+        assert!(info(json!({})).ensure_linked_to(&account).is_err());
+    }
+
+    #[test]
+    fn sync_token_info_must_be_an_object() {
+        for reply in [
+            json!(null),
+            json!("ok"),
+            json!([{ "accountId": "1" }]),
+            json!(1),
+        ] {
+            assert!(SyncTokenInfo::from_value(reply).is_err());
+        }
+    }
+
+    #[test]
+    fn unverified_statuses_keep_their_code() {
+        for code in [1, 2, 5] {
+            let status: SubscriptionStatus = serde_json::from_value(json!(code)).unwrap();
+            assert_eq!(status, SubscriptionStatus::Unknown(code));
+            assert_eq!(serde_json::to_value(status).unwrap(), json!(code));
+        }
+        assert_eq!(
+            serde_json::from_value::<SubscriptionStatus>(json!(3)).unwrap(),
+            SubscriptionStatus::Active
+        );
+    }
+
+    #[test]
+    fn unset_promo_ids_are_no_promo() {
+        let promo_id = |value: Value| {
+            serde_json::from_value::<Subscription>(
+                json!({ "id": "1", "subscriberId": "2", "promoId": value }),
+            )
+            .unwrap()
+            .promo_id
+        };
+
+        assert_eq!(promo_id(json!("0")), None);
+        assert_eq!(promo_id(json!("")), None);
+        assert_eq!(promo_id(json!(null)), None);
+        assert_eq!(promo_id(json!(0)), None);
+        assert_eq!(promo_id(json!("42")).as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn earnings_are_only_totalled_in_one_currency() {
+        let wallet = |balance: i64, currency: Option<&str>| MainWallet {
+            balance: Money::from_cents(balance),
+            currency: currency.map(str::to_string),
+            ..Default::default()
+        };
+        let transaction = |kind: i64, amount: i64, fee: i64| WalletTransaction {
+            kind: kind.into(),
+            amount: Money::from_cents(amount),
+            fee: Money::from_cents(fee),
+            ..Default::default()
+        };
+        let transactions = [
+            transaction(1, 700, 100),
+            transaction(2, 500, 0),
+            transaction(9, 300, 0),
+            transaction(9, 200, 50),
+        ];
+
+        let summary = EarningsSummary::from_transactions(
+            0,
+            1,
+            &[
+                wallet(100, Some("USD")),
+                wallet(50, None),
+                wallet(25, Some("USD")),
+            ],
+            &transactions,
+        );
+        assert_eq!(summary.currency.as_deref(), Some("USD"));
+        assert_eq!(summary.balance, Some(Money::from_cents(125)));
+        assert_eq!(summary.unlabelled_balance, Money::from_cents(50));
+        assert_eq!(summary.total, Some(Money::from_cents(1550)));
+        assert_eq!(summary.subscriptions, Money::from_cents(600));
+        assert_eq!(summary.other, Money::from_cents(450));
+        assert_eq!(
+            summary.by_type,
+            BTreeMap::from([
+                (1, Money::from_cents(600)),
+                (2, Money::from_cents(500)),
+                (9, Money::from_cents(450)),
+            ])
+        );
+
+        // Nothing says which currency, everything is assumed to be in the same one
+        let summary = EarningsSummary::from_transactions(0, 1, &[wallet(50, None)], &transactions);
+        assert_eq!(summary.currency, None);
+        assert_eq!(summary.balance, Some(Money::from_cents(50)));
+        assert_eq!(summary.total, Some(Money::from_cents(1550)));
+
+        let summary = EarningsSummary::from_transactions(
+            0,
+            1,
+            &[wallet(100, Some("USD")), wallet(50, Some("EUR"))],
+            &transactions,
+        );
+        assert_eq!(summary.currency, None);
+        assert_eq!(summary.balance, None);
+        assert_eq!(summary.total, None);
+        assert_eq!(
+            summary.balances,
+            BTreeMap::from([
+                ("EUR".to_string(), Money::from_cents(50)),
+                ("USD".to_string(), Money::from_cents(100)),
+            ])
+        );
+        assert_eq!(summary.by_type.len(), 3);
+    }
+}
@@ -7,14 +7,162 @@ export type Config = {
 	sync_interval: number;
 	last_sync: number;
 	last_sync_data: SyncData;
+	upload_profile: 'full' | 'standard' | 'minimal';
+	upload_field_allowlist: string[];
+	sync_signing_secret: string;
+	sync_subscriber_statuses: number[];
+	sync_following: boolean;
+	sync_lists: boolean;
+	sync_enrich_accounts: boolean;
+	sync_earnings: boolean;
+	sync_earnings_days: number;
+	sync_tier_report: boolean;
+	sync_history_enabled: boolean;
+	sync_history_limit: number;
+	notify_new_subscribers: boolean;
+	notify_tier_changes: boolean;
+	notify_cancellations: boolean;
+	notify_milestones: boolean;
+	notify_sync_failures: boolean;
+	notify_digest: boolean;
+	webhooks: WebhookConfig[];
+	chat_webhooks: ChatWebhook[];
+	log_json: boolean;
 };
 
+export interface ChatWebhook {
+	platform: 'discord' | 'slack';
+	url: string;
+	enabled: boolean;
+}
+
+export type WebhookEvent =
+	| 'sync.started'
+	| 'sync.completed'
+	| 'sync.failed'
+	| 'subscriber.new'
+	| 'subscriber.cancelled'
+	| 'subscriber.expired'
+	| 'subscriber.tier_changed'
+	| 'follower.new'
+	| 'follower.lost'
+	| 'milestone.reached';
+
+export interface WebhookConfig {
+	url: string;
+	secret: string;
+	events: WebhookEvent[];
+	enabled: boolean;
+}
+
+export interface DeliveryRecord {
+	host: string;
+	event: WebhookEvent;
+	timestamp: number;
+	attempts: number;
+	status: null | number;
+	success: boolean;
+	error: null | string;
+}
+
 export interface SyncData {
 	followers: Follower[];
 	subscribers: Subscriber[];
+	following?: Following[];
+	lists?: FanslyList[];
+	accounts?: Record<string, AccountSummary>;
+	earnings?: EarningsData;
+	tier_report?: TierReport;
+	follow_count: number;
+	subscriber_count: number;
 	sync_data_url: string;
 }
 
+export interface TierReport {
+	totalActive: number;
+	tiers: TierStats[];
+}
+
+export interface TierStats {
+	tierId: string;
+	name: string;
+	color: string;
+	price: number;
+	maxSubscribers: number;
+	activeSubscribers: number;
+	shareOfActive: number;
+	capacityUsed: null | number;
+	averageEffectivePrice: number;
+	averageMonthlyPrice: number;
+	billingCycles: { billingCycle: number; subscribers: number; share: number }[];
+	promoSubscribers: number;
+	promoShare: number;
+	promoUses: number;
+	onProfile: boolean;
+}
+
+export interface EarningsData {
+	wallets: Wallet[];
+	summary: EarningsSummary;
+	transactions?: WalletTransaction[];
+}
+
+interface Wallet {
+	id: string;
+	accountId: string;
+	balance: number;
+	type: number;
+	walletVersion: number;
+	flags: number;
+	currency?: string;
+}
+
+export interface EarningsSummary {
+	start: number;
+	end: number;
+	currency: null | string;
+	balance: null | number;
+	balances: Record<string, number>;
+	unlabelledBalance: number;
+	total: null | number;
+	subscriptions: number;
+	tips: number;
+	ppv: number;
+	mediaSales: number;
+	other: number;
+	byType: Record<string, number>;
+	transactionCount: number;
+}
+
+interface WalletTransaction {
+	id: string;
+	type: number;
+	amount: number;
+	fee: number;
+	senderId: null | string;
+	createdAt: number;
+}
+
+interface AccountSummary {
+	id: string;
+	username: string;
+	displayName: null | string;
+	createdAt: number;
+	avatarId: null | string;
+}
+
+interface Following {
+	accountId: string;
+}
+
+interface FanslyList {
+	id: string;
+	name: string;
+	itemCount: number;
+	createdAt: number;
+	members: string[];
+}
+
 interface Subscriber {
 	id: string;
 	historyId: string;
@@ -25,11 +173,11 @@ interface Subscriber {
 	planId: string;
 	promoId: null | string;
 	giftCodeId: null | string;
-	paymentMethodId: string;
+	paymentMethodId: null | string;
 	status: number;
 	price: number;
 	renewPrice: number;
-	renewCorrelationId: string;
+	renewCorrelationId: null | string;
 	autoRenew: number;
 	billingCycle: number;
 	duration: number;
@@ -256,3 +404,57 @@ interface Permissions {
 interface AccountPermissionFlags {
 	flags: number;
 }
+
+export type Granularity = 'daily' | 'weekly';
+
+export interface ChurnPoint {
+	periodStart: number;
+	newSubscribers: number;
+	cancellations: number;
+	expirations: number;
+	renewalsDue: number;
+	renewals: number;
+	renewalRate: null | number;
+	newFollowers: number;
+	lostFollowers: number;
+	netFollowerGrowth: number;
+	followers: number;
+	activeSubscribers: number;
+}
+
+export interface CohortRetention {
+	cohort: string;
+	size: number;
+	retention: (null | number)[];
+}
+
+export interface ForecastEntry {
+	subscriptionId: string;
+	subscriberId: string;
+	subscriptionTierId: string;
+	subscriptionTierName: string;
+	event: 'renewal' | 'expiration';
+	date: number;
+	autoRenew: number;
+	billingCycle: number;
+	renewPrice: number;
+}
+
+export interface RenewalForecast {
+	start: number;
+	end: number;
+	renewals: ForecastEntry[];
+	expirations: ForecastEntry[];
+	projectedRevenue: number;
+	atRisk: ForecastEntry[];
+}
+
+export interface FieldError {
+	path: string;
+	message: string;
+}
+
+export interface ImportSummary {
+	config: Config;
+	notes: string[];
+}